@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use nere_internal::{
+    coverage::{Coverage, CoverageReport, LineCoverage},
+    ByteCode,
+};
+
+/// Writes the coverage of a run as json
+pub fn write(coverage: &Coverage, byte_code: &ByteCode, path: &str) -> Result<(), String> {
+    let report = CoverageReport::new(coverage, byte_code);
+    let json = serde_json::to_string_pretty(&report).map_err(|err| err.to_string())?;
+    std::fs::write(path, json).map_err(|err| err.to_string())
+}
+
+/// Prints a coverage file as an annotated source listing or as lcov records
+pub fn report(path: &str, lcov: bool) -> Result<(), String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|err| format!("failed to read '{path}': {err}"))?;
+    let report: CoverageReport = serde_json::from_str(&json)
+        .map_err(|err| format!("'{path}' is not a coverage file: {err}"))?;
+
+    if lcov {
+        print!("{}", report.lcov());
+        return Ok(());
+    }
+
+    let files = report.lines();
+
+    if files.is_empty() {
+        println!("the binary was stripped, there are no source lines to show");
+    }
+
+    for (path, lines) in files.iter() {
+        print_listing(path, lines);
+    }
+
+    let ran = report.instructions.iter().filter(|i| i.hits > 0).count();
+    println!(
+        "\ninstructions: {ran}/{} ({:.2}%)",
+        report.instructions.len(),
+        percent(ran, report.instructions.len())
+    );
+
+    let total = report.branches.len() * 2;
+    let taken = report
+        .branches
+        .iter()
+        .map(|branch| (branch.taken > 0) as usize + (branch.skipped > 0) as usize)
+        .sum::<usize>();
+    println!("branches: {taken}/{total} ({:.2}%)", percent(taken, total));
+
+    Ok(())
+}
+
+fn print_listing(path: &str, lines: &BTreeMap<usize, LineCoverage>) {
+    let Ok(source) = std::fs::read_to_string(path) else {
+        println!("\n{path}: source file not found");
+        return;
+    };
+
+    let covered = lines.values().filter(|line| line.hits > 0).count();
+    println!(
+        "\n{path}: {covered}/{} lines ({:.2}%)",
+        lines.len(),
+        percent(covered, lines.len())
+    );
+
+    for (number, text) in source.lines().enumerate() {
+        let hits = match lines.get(&(number + 1)) {
+            Some(line) if line.hits == 0 => "#####".red().to_string(),
+            Some(line) => line.hits.to_string(),
+            None => String::new(),
+        };
+
+        print!("{hits:>10} | {text}");
+
+        for branch in lines.get(&(number + 1)).iter().flat_map(|l| &l.branches) {
+            let note = format!(
+                "  [if: entered {}, skipped {}]",
+                branch.taken, branch.skipped
+            );
+
+            if branch.taken == 0 || branch.skipped == 0 {
+                print!("{}", note.yellow());
+            } else {
+                print!("{note}");
+            }
+        }
+
+        println!();
+    }
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+
+    part as f64 / total as f64 * 100.0
+}
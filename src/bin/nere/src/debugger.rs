@@ -0,0 +1,74 @@
+use std::io::BufRead;
+
+use colored::Colorize;
+use nere_internal::{
+    debugger::Debugger,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+};
+
+/// Runs `binary` under the debugger, returns the exit code of the program
+pub fn run(binary: &str, program_args: Vec<String>) -> i32 {
+    let mut vm = VirtualMachine::default();
+
+    if let Err(err) = vm.load_binary(binary.to_string()) {
+        eprintln!("{err}");
+        eprintln!(
+            "{}: failed to load binary due to previous error",
+            "error".red()
+        );
+        return err.exit_code();
+    }
+
+    vm.set_args(program_args);
+
+    // the program and the debugger take turns reading from stdin
+    vm.set_input(Box::new(StdinLines::default()));
+    let mut debugger = Debugger::new(Box::new(StdinLines::default()), Box::new(std::io::stdout()));
+
+    println!("debugging '{binary}', type 'help' for a list of commands");
+
+    match vm.execute_with_hook(&RuntimeOptions::default(), &mut debugger) {
+        Ok(code) => {
+            println!("program exited with code {code}");
+            code
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            err.exit_code()
+        }
+    }
+}
+
+/// Reads stdin a line at a time without holding on to its lock in between,
+/// so that neither reader buffers input that was meant for the other one
+#[derive(Default)]
+struct StdinLines {
+    line: String,
+    consumed: usize,
+}
+
+impl std::io::Read for StdinLines {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for StdinLines {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.consumed == self.line.len() {
+            self.line.clear();
+            self.consumed = 0;
+            std::io::stdin().read_line(&mut self.line)?;
+        }
+
+        Ok(&self.line.as_bytes()[self.consumed..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.consumed = (self.consumed + amount).min(self.line.len());
+    }
+}
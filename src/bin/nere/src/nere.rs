@@ -1,27 +1,132 @@
-use runtime_args::RuntimeArgs;
-use virtual_machine::VirtualMachine;
+use std::{fs::File, io::BufWriter, path::PathBuf, time::Duration};
+
+use runtime_args::{Command, RuntimeArgs, TraceFormat};
 
 use clap::Parser;
 use colored::Colorize;
+use nere_internal::{
+    disassembler::Disassembler,
+    exit_code,
+    file_policy::FilePolicy,
+    virtual_machine::{self, RuntimeOptions, VirtualMachine},
+    Error,
+};
 
+pub mod coverage;
+pub mod debugger;
+pub mod profiler;
 pub mod runtime_args;
-pub mod virtual_machine;
+pub mod test_runner;
 
 fn main() {
     let args = RuntimeArgs::parse();
 
+    match &args.command {
+        Some(Command::Test { paths }) => {
+            if !test_runner::run(paths) {
+                std::process::exit(exit_code::RUNTIME_ERROR);
+            }
+
+            return;
+        }
+        Some(Command::Debug {
+            binary,
+            program_args,
+        }) => {
+            let code = debugger::run(binary, program_args.clone());
+            std::process::exit(code);
+        }
+        Some(Command::Dis { binary }) => {
+            let mut vm = VirtualMachine::default();
+            let loaded = std::fs::read(binary)
+                .map_err(|_| Error::InvalidFilepath(binary.clone()))
+                .and_then(|bytes| vm.load_bytes(bytes));
+
+            if let Err(err) = loaded {
+                eprintln!("{err}");
+                std::process::exit(err.exit_code());
+            }
+
+            print!("{}", Disassembler::assembly(vm.byte_code()));
+            return;
+        }
+        Some(Command::Report { coverage, lcov }) => {
+            if let Err(err) = coverage::report(coverage, *lcov) {
+                eprintln!("{}: {err}", "error".red());
+                std::process::exit(exit_code::RUNTIME_ERROR);
+            }
+
+            return;
+        }
+        None => (),
+    }
+
     let mut vm = VirtualMachine::default();
 
-    if let Err(err) = vm.load_binary(args.binary.clone()) {
+    if let Err(err) = vm.load_binary(args.binary.clone().unwrap()) {
         eprintln!("{err}");
         eprintln!(
             "{}: failed to load binary due to previous error",
             "error".red()
         );
-        return;
+        std::process::exit(err.exit_code());
+    }
+
+    vm.set_args(args.program_args.clone());
+
+    if let Some(path) = &args.trace_file {
+        match File::create(path) {
+            Ok(file) => vm.set_trace_output(Box::new(BufWriter::new(file))),
+            Err(err) => {
+                eprintln!("{}: failed to create '{path}': {err}", "error".red());
+                std::process::exit(exit_code::LOAD_ERROR);
+            }
+        }
+    }
+
+    let options = RuntimeOptions {
+        disassemble: args.disassemble,
+        stack_trace: args.stack_trace || args.trace_format.is_some(),
+        trace_format: match args.trace_format {
+            Some(TraceFormat::Json) => virtual_machine::TraceFormat::Json,
+            Some(TraceFormat::Text) | None => virtual_machine::TraceFormat::Text,
+        },
+        file_policy: FilePolicy {
+            read: args.allow_read.iter().map(PathBuf::from).collect(),
+            write: args.allow_write.iter().map(PathBuf::from).collect(),
+        },
+        fuel: args.fuel,
+        timeout: args.timeout.map(Duration::from_millis),
+        max_stack: args.max_stack,
+        max_return_stack: args.max_return_stack,
+        max_heap_bytes: args.max_heap_bytes,
+        profile: args.profile,
+        coverage: args.coverage.is_some(),
+    };
+
+    let result = vm.execute(&options);
+
+    if let Some(profile) = vm.profile() {
+        profiler::report(profile, vm.byte_code());
+
+        if let Some(path) = &args.profile_stacks {
+            if let Err(err) = profiler::write_stacks(profile, vm.byte_code(), path) {
+                eprintln!("{}: failed to write '{path}': {err}", "error".red());
+            }
+        }
+    }
+
+    if let (Some(coverage), Some(path)) = (vm.coverage(), &args.coverage) {
+        if let Err(err) = coverage::write(coverage, vm.byte_code(), path) {
+            eprintln!("{}: failed to write '{path}': {err}", "error".red());
+        }
     }
 
-    if let Err(err) = vm.execute(&args) {
-        eprint!("{err}");
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(err.exit_code());
+        }
     }
 }
@@ -0,0 +1,102 @@
+use std::{cmp::Reverse, collections::HashMap, io::Write, time::Duration};
+
+use nere_internal::{disassembler::Disassembler, profile::Profile, ByteCode, OpCode};
+
+/// The number of instructions shown in the hotspot table
+const HOTSPOTS: usize = 20;
+
+/// Prints the instructions and opcodes that took the most time
+pub fn report(profile: &Profile, byte_code: &ByteCode) {
+    let total = profile.total;
+    let mut sources = Sources::default();
+
+    println!(
+        "\nprofile: {} instructions in {:.6}s",
+        profile.instruction_count(),
+        total.as_secs_f64()
+    );
+
+    let mut hotspots = profile.instructions.iter().collect::<Vec<_>>();
+    hotspots.sort_by_key(|(_, sample)| Reverse(sample.time));
+
+    println!("\nhotspots:");
+    println!("{:>10} {:>12} {:>7}  instruction", "count", "time", "%");
+
+    for (&offset, sample) in hotspots.iter().take(HOTSPOTS) {
+        print!(
+            "{:>10} {:>11.6}s {:>6.2}%  ",
+            sample.count,
+            sample.time.as_secs_f64(),
+            percent(sample.time, total)
+        );
+
+        let mut cursor = offset;
+        let opcode = OpCode::from(byte_code.bytes[offset]);
+        Disassembler::disassemble_instruction(byte_code, opcode, &mut cursor);
+
+        let entry = byte_code
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.entry_at(offset));
+
+        if let Some(entry) = entry {
+            let line = sources.line(&entry.location.path, entry.location.line);
+            println!("{:33}{} {}", "", entry.location, line.trim());
+        }
+    }
+
+    let mut opcodes = profile.opcodes.iter().collect::<Vec<_>>();
+    opcodes.sort_by_key(|(_, sample)| Reverse(sample.time));
+
+    println!("\nopcodes:");
+    println!("{:>10} {:>12} {:>7}  opcode", "count", "time", "%");
+
+    for (&byte, sample) in opcodes.iter() {
+        println!(
+            "{:>10} {:>11.6}s {:>6.2}%  {}",
+            sample.count,
+            sample.time.as_secs_f64(),
+            percent(sample.time, total),
+            OpCode::from(byte).mnemonic()
+        );
+    }
+}
+
+/// Writes the collapsed call stacks of the profile for flame graph tools
+pub fn write_stacks(profile: &Profile, byte_code: &ByteCode, path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    for line in profile.collapsed_stacks(byte_code).iter() {
+        writeln!(file, "{line}")?;
+    }
+
+    Ok(())
+}
+
+fn percent(time: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        return 0.0;
+    }
+
+    time.as_secs_f64() / total.as_secs_f64() * 100.0
+}
+
+/// Reads every source file once, missing files have no lines
+#[derive(Default)]
+struct Sources {
+    files: HashMap<String, Vec<String>>,
+}
+
+impl Sources {
+    fn line(&mut self, path: &str, line: usize) -> &str {
+        let lines = self.files.entry(path.to_string()).or_insert_with(|| {
+            std::fs::read_to_string(path)
+                .map(|source| source.lines().map(str::to_string).collect())
+                .unwrap_or_default()
+        });
+
+        lines
+            .get(line.saturating_sub(1))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
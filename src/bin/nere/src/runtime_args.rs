@@ -1,14 +1,20 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[command(
     author = "https://github.com/Jshuk-7",
     version = "0.1.0",
-    about = "nere programming language runtime"
+    about = "nere programming language runtime",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 pub struct RuntimeArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// The compiled program to run
-    pub binary: String,
+    #[arg(required = true)]
+    pub binary: Option<String>,
 
     /// Show a breakdown of the bytecode during execution
     #[arg(short = 'd', long = "disassemble")]
@@ -17,4 +23,99 @@ pub struct RuntimeArgs {
     /// Show a breakdown of the stack during execution
     #[arg(short = 's', long = "stack-trace")]
     pub stack_trace: bool,
+
+    /// Trace the stack in this format, implies '--stack-trace'
+    #[arg(long = "trace-format", value_name = "FORMAT")]
+    pub trace_format: Option<TraceFormat>,
+
+    /// Write the stack trace to this file instead of mixing it into the
+    /// program output, e.g. '/dev/fd/3' or 'trace.jsonl'
+    #[arg(long = "trace-file", value_name = "FILE")]
+    pub trace_file: Option<String>,
+
+    /// Stop the program after it has run this many instructions
+    #[arg(long = "fuel", value_name = "INSTRUCTIONS")]
+    pub fuel: Option<u64>,
+
+    /// Stop the program after it has run for this many milliseconds
+    #[arg(long = "timeout", value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// Limit the number of values on the operand stack
+    #[arg(long = "max-stack", value_name = "VALUES")]
+    pub max_stack: Option<usize>,
+
+    /// Limit how deep quotation calls may nest
+    #[arg(long = "max-return-stack", value_name = "FRAMES")]
+    pub max_return_stack: Option<usize>,
+
+    /// Limit the bytes taken up by strings, lists, maps and structs
+    #[arg(long = "max-heap", value_name = "BYTES")]
+    pub max_heap_bytes: Option<usize>,
+
+    /// Allow the program to read files inside of this directory, can be repeated
+    #[arg(long = "allow-read", value_name = "DIR")]
+    pub allow_read: Vec<String>,
+
+    /// Allow the program to create, write and delete files inside of this directory, can be repeated
+    #[arg(long = "allow-write", value_name = "DIR")]
+    pub allow_write: Vec<String>,
+
+    /// Print the instructions and opcodes the program spent the most time on
+    #[arg(long = "profile")]
+    pub profile: bool,
+
+    /// Write the instructions run under each call stack to this file, in the
+    /// collapsed format read by flamegraph tools
+    #[arg(long = "profile-stacks", value_name = "FILE", requires = "profile")]
+    pub profile_stacks: Option<String>,
+
+    /// Write the instructions, source lines and branches the program ran to this
+    /// file as json, render it with 'nere report'
+    #[arg(long = "coverage", value_name = "FILE")]
+    pub coverage: Option<String>,
+
+    /// Arguments passed on to the program, e.g. 'nere prog.out -- a b c'
+    #[arg(last = true)]
+    pub program_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TraceFormat {
+    /// The stack after each instruction, '[ 1 ] [ 2 ]'
+    Text,
+    /// One json object per line with the ip, opcode, operands and stack
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the test blocks of every '*_test.nere' file
+    Test {
+        /// Files or directories to search for tests, defaults to the current directory
+        paths: Vec<String>,
+    },
+    /// Run a compiled program one instruction at a time
+    Debug {
+        /// The compiled program to debug
+        binary: String,
+
+        /// Arguments passed on to the program
+        #[arg(last = true)]
+        program_args: Vec<String>,
+    },
+    /// Print a compiled program as assembly that 'nereasm' turns back into the same bytes
+    Dis {
+        /// The compiled program to disassemble
+        binary: String,
+    },
+    /// Render a file written by '--coverage' as an annotated source listing
+    Report {
+        /// The coverage file to render
+        coverage: String,
+
+        /// Print lcov tracefile records instead of a listing
+        #[arg(long = "lcov")]
+        lcov: bool,
+    },
 }
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use nere_internal::{
+    compiler::{Compiler, TestBlock},
+    exit_code,
+    timer::Timer,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+    Error, Token, Value,
+};
+
+const TEST_SUFFIX: &str = "_test.nere";
+
+/// Runs every test block found in `paths`, returns true when all of them passed
+pub fn run(paths: &[String]) -> bool {
+    let timer = Timer::default();
+
+    let paths = if paths.is_empty() {
+        vec![".".to_string()]
+    } else {
+        paths.to_vec()
+    };
+
+    let mut files = vec![];
+    for path in paths.iter() {
+        discover(Path::new(path), &mut files);
+    }
+    files.sort();
+    files.dedup();
+
+    let mut passed = 0;
+    let mut failures = vec![];
+
+    for file in files.iter() {
+        let path = file.strip_prefix(".").unwrap_or(file).display().to_string();
+        let compiler = Compiler::default();
+
+        let split = compiler
+            .scan_file(&path)
+            .and_then(|tokens| compiler.split_tests(tokens));
+
+        let (program, tests) = match split {
+            Ok(split) => split,
+            Err(err) => {
+                println!("{} '{path}' ... {}", "error in".red(), "FAILED".red());
+                failures.push((path.clone(), err));
+                continue;
+            }
+        };
+
+        println!("\nrunning {} tests in '{path}'", tests.len());
+
+        let top_level = match run_top_level(&compiler, &program) {
+            Ok(top_level) => top_level,
+            Err(err) => {
+                println!("{} '{path}' ... {}", "error in".red(), "FAILED".red());
+                failures.push((path.clone(), err));
+                continue;
+            }
+        };
+
+        for test in tests.iter() {
+            print!("test {} ... ", test.name);
+            let timer = Timer::default();
+
+            match run_test(&compiler, &program, &top_level, test) {
+                Ok(()) => {
+                    println!("{} in {}s", "ok".green(), timer.elapsed());
+                    passed += 1;
+                }
+                Err(err) => {
+                    println!("{} in {}s", "FAILED".red(), timer.elapsed());
+                    failures.push((format!("{path}: {}", test.name), err));
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+
+        for (name, err) in failures.iter() {
+            println!("\n---- {name} ----\n{err}");
+        }
+    }
+
+    let result = if failures.is_empty() {
+        "ok".green()
+    } else {
+        "FAILED".red()
+    };
+
+    println!(
+        "\ntest result: {result}. {passed} passed; {} failed; finished in {}s",
+        failures.len(),
+        timer.elapsed()
+    );
+
+    failures.is_empty()
+}
+
+/// What the code outside of any test block left behind after running once
+struct TopLevel {
+    /// Its instructions, which every test program starts with
+    code: Vec<u8>,
+    stack: Vec<Value>,
+}
+
+/// Runs the code outside of any test block once, on its own
+fn run_top_level(compiler: &Compiler, program: &[Token]) -> Result<TopLevel, Error> {
+    let bytes = compiler.compile_tokens(program.to_vec())?;
+
+    let mut vm = VirtualMachine::default();
+    vm.load_bytes(bytes)?;
+
+    let code = vm.execute(&RuntimeOptions::default())?;
+
+    // an 'exit' stops the top level code before the end of the program
+    if code != exit_code::SUCCESS || vm.ip() < vm.byte_code().bytes.len() {
+        return Err(Error::RuntimeError(format!(
+            "the code outside of test blocks exited with code {code} before the tests ran"
+        )));
+    }
+
+    // the tests continue from where the top level code halted
+    let mut code = vm.byte_code().bytes.clone();
+    code.pop();
+
+    Ok(TopLevel {
+        code,
+        stack: vm.stack().to_vec(),
+    })
+}
+
+/// Compiles a test block together with the code outside of any test
+/// block, then runs only the test on a fresh vm with the stack the top
+/// level code left behind
+fn run_test(
+    compiler: &Compiler,
+    program: &[Token],
+    top_level: &TopLevel,
+    test: &TestBlock,
+) -> Result<(), Error> {
+    let (eof, setup) = program.split_last().unwrap();
+
+    let mut tokens = setup.to_vec();
+    tokens.extend(test.body.iter().cloned());
+    tokens.push(eof.clone());
+
+    let bytes = compiler.compile_tokens(tokens)?;
+
+    let mut vm = VirtualMachine::default();
+    vm.load_bytes(bytes)?;
+
+    if !vm.byte_code().bytes.starts_with(&top_level.code) {
+        return Err(Error::RuntimeError(
+            "the test changes how the code outside of test blocks compiles".to_string(),
+        ));
+    }
+
+    let start = top_level.code.len();
+    match vm.execute_from(start, top_level.stack.clone(), &RuntimeOptions::default())? {
+        exit_code::SUCCESS => Ok(()),
+        code => Err(Error::RuntimeError(format!("test exited with code {code}"))),
+    }
+}
+
+fn discover(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            discover(&path, files);
+        } else if path.to_string_lossy().ends_with(TEST_SUFFIX) {
+            files.push(path);
+        }
+    }
+}
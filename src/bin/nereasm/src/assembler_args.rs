@@ -0,0 +1,16 @@
+use clap::Parser;
+
+#[derive(Debug, Parser)]
+#[command(
+    author = "https://github.com/Jshuk-7",
+    version = "0.1.0",
+    about = "nere assembler, reads the syntax written by 'nere dis'"
+)]
+pub struct AssemblerArgs {
+    /// The assembly file to assemble, reads stdin when it is '-' or left out
+    pub input: Option<String>,
+
+    /// The output file of the assembler
+    #[arg(short = 'o', long = "output", default_value = "a.out")]
+    pub output: String,
+}
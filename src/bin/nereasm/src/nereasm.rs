@@ -0,0 +1,52 @@
+use std::io::Read;
+
+use assembler_args::AssemblerArgs;
+use nere_internal::{assembler::Assembler, timer::Timer, Error};
+
+use clap::Parser;
+use colored::Colorize;
+
+pub mod assembler_args;
+
+const STDIN: &str = "-";
+
+fn assemble(args: &AssemblerArgs) -> Result<(), Error> {
+    let timer = Timer::default();
+    let input = args.input.as_deref().unwrap_or(STDIN);
+
+    let source = if input == STDIN {
+        let mut source = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|_| Error::InvalidUTF8String)?;
+        source
+    } else {
+        std::fs::read_to_string(input).map_err(|_| Error::InvalidFilepath(input.to_string()))?
+    };
+
+    let name = if input == STDIN { "<stdin>" } else { input };
+    eprintln!("{} '{name}' -> '{}'", "Assembling".green(), args.output);
+
+    let bytes = Assembler::assemble(&source, name)?;
+
+    if std::fs::write(&args.output, bytes).is_err() {
+        return Err(Error::FailedToCreateFile(args.output.clone()));
+    }
+
+    eprintln!("{} '{name}' in {}s", "Finished".green(), timer.elapsed());
+
+    Ok(())
+}
+
+fn main() {
+    let args = AssemblerArgs::parse();
+
+    if let Err(err) = assemble(&args) {
+        eprintln!("{err}");
+        eprintln!(
+            "{}: failed to assemble program due to previous error",
+            "error".red()
+        );
+        std::process::exit(err.exit_code());
+    }
+}
@@ -24,4 +24,8 @@ pub struct CompilerArgs {
     /// Show a breakdown of the bytecode after compilation
     #[arg(short = 'd', long = "disassemble")]
     pub disassemble: bool,
+
+    /// Leave out the debug info that maps bytecode back to source locations
+    #[arg(short = 's', long = "strip")]
+    pub strip: bool,
 }
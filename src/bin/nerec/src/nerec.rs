@@ -1,22 +1,63 @@
-use compiler::Compiler;
+use std::{fs::File, io::Write, path::Path};
+
 use compiler_args::CompilerArgs;
+use nere_internal::{compiler::Compiler, timer::Timer, utils, Error};
 
 use clap::Parser;
 use colored::Colorize;
 
-pub mod compiler;
 pub mod compiler_args;
 
+fn compile(args: &CompilerArgs) -> Result<(), Error> {
+    let timer = Timer::default();
+    let input = utils::filename_from_path(&args.input);
+
+    if !Path::new(&input).exists() {
+        return Err(Error::InvalidFilepath(input));
+    }
+
+    if !input.ends_with(".nere") {
+        let ext = utils::extension_from_path(&input);
+        return Err(Error::InvalidExtension(ext));
+    }
+
+    let out = args.output.clone();
+    let output = utils::filename_from_path(out.as_deref().unwrap_or("a.out"));
+
+    println!("{} '{input}' -> '{output}'", "Compiling".green(),);
+
+    let compiler = Compiler {
+        display_tokens: args.display_tokens,
+        disassemble: args.disassemble,
+        strip: args.strip,
+    };
+
+    let tokens = compiler.scan_file(&input)?;
+    let bytes = compiler.compile_tokens(tokens)?;
+
+    match File::create(output.clone()) {
+        Ok(mut file) => {
+            file.write_all(&bytes).unwrap();
+        }
+        Err(..) => {
+            return Err(Error::FailedToCreateFile(output));
+        }
+    }
+
+    println!("{} '{}' in {}s", "Finished".green(), input, timer.elapsed());
+
+    Ok(())
+}
+
 fn main() {
     let args = CompilerArgs::parse();
 
-    let compiler = Compiler::default();
-
-    if let Err(err) = compiler.compile(&args) {
+    if let Err(err) = compile(&args) {
         eprintln!("{err}");
         eprintln!(
             "{}: failed to compile program due to previous error",
             "error".red()
         );
+        std::process::exit(err.exit_code());
     }
 }
@@ -0,0 +1,540 @@
+//! Turns nere assembly into a binary, the syntax is the one written by
+//! `nere dis` so that disassembling and assembling a binary gives back the
+//! same bytes.
+//!
+//! A line holds a directive, a label or an instruction, and everything after
+//! a `;` that isn't inside of a string is a comment:
+//!
+//! ```text
+//! ; constants are numbered from 0 and must be declared in order
+//! .const 0 i32 -7
+//! .const 1 u32 7
+//! .const 2 str "a \"quoted\" line\n"
+//! .const 3 list [ i32 1 list [ str "nested" ] ]
+//!
+//! ; struct types are numbered in the order they are declared, the name comes first
+//! .struct "Point" "x" "y"
+//!
+//! ; native functions are numbered in the order they are declared
+//! .native "host-name"
+//!
+//! start:
+//!     .loc "main.nere" 1 1 "1"
+//!     push 0 ; pushes constant 0
+//!     dup
+//!     if start
+//!     halt
+//! ```
+//!
+//! - instructions are the kebab case names of the opcodes, e.g. `add`, `map-get-or`,
+//!   `call-native`, followed by their operands, the 8 byte words after the opcode
+//! - an operand is an integer or a label, which stands for the offset of the
+//!   instruction that follows it
+//! - constant, struct, field and native operands must refer to something that
+//!   was declared and jumps must land on an instruction
+//! - labels are a name followed by `:`, names are made of letters, digits, `_` and `-`
+//! - `.loc "path" line column "symbol"` adds a line table entry for the next
+//!   instruction, a binary without any `.loc` has no debug info section
+//! - strings support the escapes `\"`, `\\`, `\n`, `\r` and `\t`
+//! - a `halt` is added when the last instruction isn't one, the vm needs it to
+//!   know where the code ends
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    compiler::Compiler, ByteCode, DebugInfo, Error, LineEntry, Location, OpCode, StructDef, Value,
+};
+
+pub type AssembleResult<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Word {
+    Text(String),
+    Str(String),
+    LBracket,
+    RBracket,
+}
+
+enum Operand {
+    Int(isize),
+    Label(String, Location),
+}
+
+struct Instruction {
+    opcode: OpCode,
+    operands: Vec<Operand>,
+    location: Location,
+}
+
+#[derive(Default)]
+pub struct Assembler {
+    byte_code: ByteCode,
+    debug_info: DebugInfo,
+    instructions: Vec<Instruction>,
+    labels: HashMap<String, usize>,
+    /// The offset the next instruction will be at
+    offset: usize,
+}
+
+impl Assembler {
+    /// Assembles the text of `source`, `path` is only used in error messages
+    pub fn assemble(source: &str, path: &str) -> AssembleResult<Vec<u8>> {
+        let mut assembler = Assembler::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let location = Location {
+                path: path.to_string(),
+                line: number + 1,
+                column: 1,
+            };
+
+            let words = Assembler::split_words(line, &location)?;
+            assembler.assemble_line(words)?;
+        }
+
+        if !matches!(
+            assembler.instructions.last(),
+            Some(Instruction {
+                opcode: OpCode::Halt,
+                ..
+            })
+        ) {
+            assembler.instructions.push(Instruction {
+                opcode: OpCode::Halt,
+                operands: vec![],
+                location: Location {
+                    path: path.to_string(),
+                    line: source.lines().count(),
+                    column: 1,
+                },
+            });
+        }
+
+        assembler.write_code()?;
+        assembler.validate()?;
+
+        let mut byte_code = assembler.byte_code;
+
+        // the code starts with the index of its final halt like a compiled program
+        let halt_index = byte_code.bytes.len() - 1;
+        let halt_index_bytes: [u8; 8] = halt_index.to_ne_bytes();
+        byte_code.bytes.splice(0..0, halt_index_bytes);
+
+        if !assembler.debug_info.lines.is_empty() {
+            byte_code.debug_info = Some(assembler.debug_info);
+        }
+
+        Ok(Compiler::default().write_binary(byte_code))
+    }
+
+    fn assemble_line(&mut self, words: Vec<(Word, Location)>) -> AssembleResult<()> {
+        let mut words = words.into_iter();
+
+        let Some((first, location)) = words.next() else {
+            return Ok(());
+        };
+
+        let Word::Text(first) = first else {
+            return Err(Error::CompileError(
+                "expected a directive, a label or an instruction".to_string(),
+                location,
+            ));
+        };
+
+        let rest = words.collect::<Vec<_>>();
+
+        if let Some(label) = first.strip_suffix(':') {
+            if !Assembler::is_name(label) {
+                return Err(Error::CompileError(
+                    format!("'{label}' is not a valid label name"),
+                    location,
+                ));
+            }
+
+            if let Some((_, location)) = rest.first() {
+                return Err(Error::CompileError(
+                    "a label must be on a line of its own".to_string(),
+                    location.clone(),
+                ));
+            }
+
+            if self.labels.insert(label.to_string(), self.offset).is_some() {
+                return Err(Error::CompileError(
+                    format!("label '{label}' is defined more than once"),
+                    location,
+                ));
+            }
+
+            return Ok(());
+        }
+
+        match first.as_str() {
+            ".const" => self.assemble_constant(rest, &location),
+            ".struct" => {
+                let mut names = Assembler::strings(rest)?;
+
+                if names.is_empty() {
+                    return Err(Error::CompileError(
+                        "expected a struct name after '.struct'".to_string(),
+                        location,
+                    ));
+                }
+
+                let name = names.remove(0);
+                self.byte_code.structs.push(Rc::new(StructDef {
+                    name,
+                    fields: names,
+                }));
+
+                Ok(())
+            }
+            ".native" => match Assembler::strings(rest)?.as_slice() {
+                [name] => {
+                    self.byte_code.natives.push(name.clone());
+                    Ok(())
+                }
+                _ => Err(Error::CompileError(
+                    "expected a single name after '.native'".to_string(),
+                    location,
+                )),
+            },
+            ".loc" => self.assemble_loc(rest, &location),
+            directive if directive.starts_with('.') => Err(Error::CompileError(
+                format!("unknown directive '{directive}'"),
+                location,
+            )),
+            mnemonic => {
+                let Some(opcode) = OpCode::from_mnemonic(mnemonic) else {
+                    return Err(Error::CompileError(
+                        format!("unknown instruction '{mnemonic}'"),
+                        location,
+                    ));
+                };
+
+                let expected = (opcode.instruction_size() - 1) / 8;
+                if rest.len() != expected {
+                    return Err(Error::CompileError(
+                        format!(
+                            "'{mnemonic}' takes {expected} operands but {} were given",
+                            rest.len()
+                        ),
+                        location,
+                    ));
+                }
+
+                let mut operands = vec![];
+                for (word, location) in rest {
+                    operands.push(match word {
+                        Word::Text(text) => match text.parse::<isize>() {
+                            Ok(int) => Operand::Int(int),
+                            Err(..) if Assembler::is_name(&text) => Operand::Label(text, location),
+                            Err(..) => {
+                                return Err(Error::CompileError(
+                                    format!("expected an integer or a label but found '{text}'"),
+                                    location,
+                                ));
+                            }
+                        },
+                        _ => {
+                            return Err(Error::CompileError(
+                                "expected an integer or a label".to_string(),
+                                location,
+                            ));
+                        }
+                    });
+                }
+
+                self.offset += opcode.instruction_size();
+                self.instructions.push(Instruction {
+                    opcode,
+                    operands,
+                    location,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn assemble_constant(
+        &mut self,
+        words: Vec<(Word, Location)>,
+        location: &Location,
+    ) -> AssembleResult<()> {
+        let mut words = words.into_iter().peekable();
+
+        let Some((Word::Text(index), index_location)) = words.next() else {
+            return Err(Error::CompileError(
+                "expected a constant index after '.const'".to_string(),
+                location.clone(),
+            ));
+        };
+
+        let next = self.byte_code.constants.len();
+
+        if index.parse::<usize>() != Ok(next) {
+            return Err(Error::CompileError(
+                format!(
+                    "expected constant {next} but found '{index}', constants are declared in order"
+                ),
+                index_location,
+            ));
+        }
+
+        let value = Assembler::constant(&mut words, location)?;
+
+        if let Some((_, location)) = words.next() {
+            return Err(Error::CompileError(
+                "unexpected input after the constant".to_string(),
+                location,
+            ));
+        }
+
+        self.byte_code.constants.push(value);
+        Ok(())
+    }
+
+    fn constant(
+        words: &mut std::iter::Peekable<std::vec::IntoIter<(Word, Location)>>,
+        location: &Location,
+    ) -> AssembleResult<Value> {
+        let Some((Word::Text(typ3), location)) = words.next() else {
+            return Err(Error::CompileError(
+                "expected a constant type, 'i32', 'u32', 'str' or 'list'".to_string(),
+                location.clone(),
+            ));
+        };
+
+        let value = words.next();
+
+        match (typ3.as_str(), value) {
+            ("i32", Some((Word::Text(text), location))) => match text.parse::<i32>() {
+                Ok(int32) => Ok(Value::Int32(int32)),
+                Err(..) => Err(Error::CompileError(
+                    format!("'{text}' is not an i32"),
+                    location,
+                )),
+            },
+            ("u32", Some((Word::Text(text), location))) => match text.parse::<u32>() {
+                Ok(uint32) => Ok(Value::UInt32(uint32)),
+                Err(..) => Err(Error::CompileError(
+                    format!("'{text}' is not a u32"),
+                    location,
+                )),
+            },
+            ("str", Some((Word::Str(string), _))) => Ok(Value::String(string)),
+            ("list", Some((Word::LBracket, _))) => {
+                let mut items = vec![];
+
+                loop {
+                    match words.peek() {
+                        Some((Word::RBracket, _)) => {
+                            words.next();
+                            return Ok(Value::List(Rc::new(items)));
+                        }
+                        Some(..) => items.push(Assembler::constant(words, &location)?),
+                        None => {
+                            return Err(Error::CompileError(
+                                "expected ']' at the end of the list".to_string(),
+                                location,
+                            ));
+                        }
+                    }
+                }
+            }
+            (typ3 @ ("i32" | "u32" | "str" | "list"), _) => Err(Error::CompileError(
+                format!("expected a value after '{typ3}'"),
+                location,
+            )),
+            (typ3, _) => Err(Error::CompileError(
+                format!("unknown constant type '{typ3}'"),
+                location,
+            )),
+        }
+    }
+
+    fn assemble_loc(
+        &mut self,
+        words: Vec<(Word, Location)>,
+        location: &Location,
+    ) -> AssembleResult<()> {
+        let words = words.into_iter().map(|(word, _)| word).collect::<Vec<_>>();
+
+        let [Word::Str(path), Word::Text(line), Word::Text(column), Word::Str(symbol)] =
+            words.as_slice()
+        else {
+            return Err(Error::CompileError(
+                "expected '.loc \"path\" line column \"symbol\"'".to_string(),
+                location.clone(),
+            ));
+        };
+
+        let (Ok(line), Ok(column)) = (line.parse::<usize>(), column.parse::<usize>()) else {
+            return Err(Error::CompileError(
+                "the line and column of '.loc' must be numbers".to_string(),
+                location.clone(),
+            ));
+        };
+
+        self.debug_info.lines.push(LineEntry {
+            offset: self.offset,
+            location: Location {
+                path: path.clone(),
+                line,
+                column,
+            },
+            symbol: symbol.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Writes every instruction now that the offsets of all labels are known
+    fn write_code(&mut self) -> AssembleResult<()> {
+        let mut bytes = vec![];
+
+        for instruction in self.instructions.iter() {
+            bytes.push(instruction.opcode.as_byte());
+
+            for operand in instruction.operands.iter() {
+                let value = match operand {
+                    Operand::Int(int) => *int,
+                    Operand::Label(label, location) => match self.labels.get(label) {
+                        Some(offset) => *offset as isize,
+                        None => {
+                            return Err(Error::CompileError(
+                                format!("undefined label '{label}'"),
+                                location.clone(),
+                            ));
+                        }
+                    },
+                };
+
+                let value_bytes: [u8; 8] = value.to_ne_bytes();
+                bytes.extend_from_slice(&value_bytes);
+            }
+        }
+
+        self.byte_code.bytes = bytes;
+        Ok(())
+    }
+
+    /// Rejects operands that refer to missing constants, structs or natives
+    /// and jumps that don't land on an instruction
+    fn validate(&self) -> AssembleResult<()> {
+        let Err(invalid) = self.byte_code.validate() else {
+            return Ok(());
+        };
+
+        let mut offset = 0;
+        let instruction = self.instructions.iter().find(|instruction| {
+            let found = offset == invalid.offset;
+            offset += instruction.opcode.instruction_size();
+            found
+        });
+
+        Err(Error::CompileError(
+            invalid.reason,
+            instruction.unwrap().location.clone(),
+        ))
+    }
+
+    /// Splits a line into words, dropping its comment
+    fn split_words(line: &str, location: &Location) -> AssembleResult<Vec<(Word, Location)>> {
+        let mut words = vec![];
+        let mut chars = line.char_indices().peekable();
+
+        while let Some(&(start, c)) = chars.peek() {
+            let location = Location {
+                column: start + 1,
+                ..location.clone()
+            };
+
+            match c {
+                ';' => break,
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '[' => {
+                    chars.next();
+                    words.push((Word::LBracket, location));
+                }
+                ']' => {
+                    chars.next();
+                    words.push((Word::RBracket, location));
+                }
+                '"' => {
+                    chars.next();
+                    let mut string = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => match chars.next() {
+                                Some((_, '"')) => string.push('"'),
+                                Some((_, '\\')) => string.push('\\'),
+                                Some((_, 'n')) => string.push('\n'),
+                                Some((_, 'r')) => string.push('\r'),
+                                Some((_, 't')) => string.push('\t'),
+                                Some((_, c)) => {
+                                    return Err(Error::CompileError(
+                                        format!("unknown escape '\\{c}'"),
+                                        location,
+                                    ));
+                                }
+                                None => {
+                                    return Err(Error::CompileError(
+                                        "unterminated string".to_string(),
+                                        location,
+                                    ));
+                                }
+                            },
+                            Some((_, c)) => string.push(c),
+                            None => {
+                                return Err(Error::CompileError(
+                                    "unterminated string".to_string(),
+                                    location,
+                                ));
+                            }
+                        }
+                    }
+
+                    words.push((Word::Str(string), location));
+                }
+                _ => {
+                    let mut text = String::new();
+
+                    while let Some((_, c)) =
+                        chars.next_if(|&(_, c)| !c.is_whitespace() && !"[]\";".contains(c))
+                    {
+                        text.push(c);
+                    }
+
+                    words.push((Word::Text(text), location));
+                }
+            }
+        }
+
+        Ok(words)
+    }
+
+    fn strings(words: Vec<(Word, Location)>) -> AssembleResult<Vec<String>> {
+        words
+            .into_iter()
+            .map(|(word, location)| match word {
+                Word::Str(string) => Ok(string),
+                _ => Err(Error::CompileError(
+                    "expected a quoted name".to_string(),
+                    location,
+                )),
+            })
+            .collect()
+    }
+
+    fn is_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }
+}
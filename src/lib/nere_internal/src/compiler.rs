@@ -0,0 +1,1345 @@
+use std::{collections::HashMap, rc::Rc};
+
+use colored::Colorize;
+
+use crate::{
+    disassembler::Disassembler, format::Format, lexer::Lexer, ByteCode, DebugInfo, Error,
+    LineEntry, Location, OpCode, StructDef, Token, TokenType, Value,
+};
+
+pub type CompileResult<T> = std::result::Result<T, Error>;
+
+enum Block {
+    If(usize),
+    Else(usize),
+    Loop,
+    Plain,
+    /// A quotation with the number of loops that enclose it, which
+    /// 'break' and 'continue' inside of the quotation can't jump out to
+    Quote(usize, usize),
+    /// A try block with the number of loops that enclose it, jumping out
+    /// to those would leave its handler installed
+    Try(usize, usize),
+    Catch(usize),
+}
+
+struct Loop {
+    label: Option<String>,
+    counted: bool,
+    condition_ip: usize,
+    entry_index: Option<usize>,
+    /// The number of list literals open around the loop
+    lists: usize,
+    breaks: Vec<(usize, isize)>,
+    continues: Vec<(usize, isize)>,
+}
+
+/// A block that the format string check follows the stack depth through
+struct DepthBlock {
+    /// The depth on the paths that go around the block, merged with the
+    /// depth at its end once it closes
+    skip: Option<usize>,
+    /// The depth that an 'else' or 'catch' block after this one starts at
+    next: Option<usize>,
+    /// A 'break' or 'continue' inside leaves the block at any depth
+    jumps: bool,
+    /// Quotations end at ':]' and only push themselves onto the outer stack
+    quotation: bool,
+}
+
+/// A block of code run on its own by 'nere test', `test "name" { ... }`
+pub struct TestBlock {
+    pub name: String,
+    pub location: Location,
+    pub body: Vec<Token>,
+}
+
+#[derive(Default)]
+pub struct Compiler {
+    /// Display language tokens during compilation
+    pub display_tokens: bool,
+    /// Show a breakdown of the bytecode after compilation
+    pub disassemble: bool,
+    /// Leave the debug info section out of the binary
+    pub strip: bool,
+}
+
+impl Compiler {
+    /// Scans a source file into tokens, reporting every parse error at once
+    pub fn scan_file(&self, input: &str) -> CompileResult<Vec<Token>> {
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.scan_tokens();
+
+        let error_tokens = tokens
+            .iter()
+            .filter(|t| matches!(t.typ3, TokenType::Error))
+            .collect::<Vec<_>>();
+
+        if !error_tokens.is_empty() {
+            let mut err_str = String::new();
+
+            for (i, token) in error_tokens.iter().enumerate() {
+                let err_type = format!("{} {}: ", token.location, "parse error".red());
+                err_str.push_str(&err_type);
+                err_str.push_str(&token.lexeme);
+
+                if i != error_tokens.len() - 1 {
+                    err_str.push('\n');
+                }
+            }
+
+            return Err(Error::ParseError(err_str));
+        }
+
+        Ok(tokens)
+    }
+
+    /// Compiles a program into the bytes of a binary, test blocks are left out
+    pub fn compile_tokens(&self, tokens: Vec<Token>) -> CompileResult<Vec<u8>> {
+        let (tokens, _) = self.split_tests(tokens)?;
+
+        let mut byte_code = ByteCode::default();
+
+        let tokens = self.fold_constant_lists(tokens);
+        let mut tokens = self.resolve_words(tokens, &mut byte_code)?;
+
+        self.preprocess_program(&mut tokens)?;
+        self.verify_cross_reference_blocks(&tokens)?;
+        self.verify_format_strings(&tokens)?;
+
+        let mut debug_info = DebugInfo::default();
+
+        for token in tokens.iter() {
+            if self.display_tokens {
+                println!("{token}");
+            }
+
+            // labels don't produce any bytes
+            if !matches!(token.typ3, TokenType::Label(..)) {
+                debug_info.lines.push(LineEntry {
+                    offset: byte_code.bytes.len(),
+                    location: token.location.clone(),
+                    symbol: token.lexeme.clone(),
+                });
+            }
+
+            self.bytes_from_token(&mut byte_code, token);
+        }
+
+        if self.disassemble {
+            Disassembler::disassemble_byte_code(&byte_code);
+        }
+
+        if !self.strip {
+            byte_code.debug_info = Some(debug_info);
+        }
+
+        Ok(self.write_binary(byte_code))
+    }
+
+    /// Appends the constant, struct, native and debug info sections to
+    /// code that already starts with its halt index
+    pub fn write_binary(&self, mut byte_code: ByteCode) -> Vec<u8> {
+        let mut constant_bytes = self.constants_to_bytes(&byte_code.constants);
+        byte_code.bytes.append(&mut constant_bytes);
+
+        let mut struct_bytes = self.structs_to_bytes(&byte_code.structs);
+        byte_code.bytes.append(&mut struct_bytes);
+
+        let mut native_bytes = self.natives_to_bytes(&byte_code.natives);
+        byte_code.bytes.append(&mut native_bytes);
+
+        if let Some(debug_info) = &byte_code.debug_info {
+            let mut debug_bytes = self.debug_info_to_bytes(debug_info);
+            byte_code.bytes.append(&mut debug_bytes);
+        }
+
+        byte_code.bytes
+    }
+
+    /// Separates the test blocks from the rest of the program
+    pub fn split_tests(&self, tokens: Vec<Token>) -> CompileResult<(Vec<Token>, Vec<TestBlock>)> {
+        let mut program = vec![];
+        let mut tests: Vec<TestBlock> = vec![];
+        let mut i = 0;
+
+        let is_test =
+            |token: &Token| matches!(&token.typ3, TokenType::Word(word) if word == "test");
+
+        while i < tokens.len() {
+            if !is_test(&tokens[i]) {
+                program.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let name = match &tokens[i + 1].typ3 {
+                TokenType::Value(Value::String(name)) => name.clone(),
+                _ => {
+                    return Err(Error::CompileError(
+                        "expected a test name after 'test'".to_string(),
+                        tokens[i + 1].location.clone(),
+                    ));
+                }
+            };
+
+            if !matches!(
+                tokens.get(i + 2).map(|t| &t.typ3),
+                Some(TokenType::Instruction(OpCode::LBrace))
+            ) {
+                return Err(Error::CompileError(
+                    format!("expected '{{' after 'test \"{name}\"'"),
+                    tokens[i + 1].location.clone(),
+                ));
+            }
+
+            if tests.iter().any(|test| test.name == name) {
+                return Err(Error::CompileError(
+                    format!("test '{name}' is already defined"),
+                    tokens[i + 1].location.clone(),
+                ));
+            }
+
+            let mut depth = 0;
+            let mut j = i + 2;
+
+            loop {
+                match &tokens[j].typ3 {
+                    TokenType::Instruction(OpCode::LBrace) => depth += 1,
+                    TokenType::Instruction(OpCode::RBrace(..)) => {
+                        depth -= 1;
+
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    TokenType::Eof => {
+                        return Err(Error::CompileError(
+                            format!("unterminated test '{name}', expected '}}'"),
+                            tokens[i].location.clone(),
+                        ));
+                    }
+                    _ if is_test(&tokens[j]) => {
+                        return Err(Error::CompileError(
+                            "test blocks can not be nested".to_string(),
+                            tokens[j].location.clone(),
+                        ));
+                    }
+                    _ => (),
+                }
+
+                j += 1;
+            }
+
+            tests.push(TestBlock {
+                name,
+                location: tokens[i].location.clone(),
+                body: tokens[i + 3..j].to_vec(),
+            });
+
+            i = j + 1;
+        }
+
+        Ok((program, tests))
+    }
+
+    /// Turns list literals made up of constants only into a single constant
+    fn fold_constant_lists(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut result: Vec<Token> = vec![];
+        let mut starts = vec![];
+
+        for token in tokens {
+            match token.typ3 {
+                TokenType::Instruction(OpCode::ListStart) => {
+                    starts.push(result.len());
+                    result.push(token);
+                }
+                TokenType::Instruction(OpCode::ListEnd) if !starts.is_empty() => {
+                    let start = starts.pop().unwrap();
+                    let is_constant = result[start + 1..]
+                        .iter()
+                        .all(|t| matches!(t.typ3, TokenType::Value(..)));
+
+                    if !is_constant {
+                        result.push(token);
+                        continue;
+                    }
+
+                    let items = result
+                        .drain(start + 1..)
+                        .map(|t| match t.typ3 {
+                            TokenType::Value(value) => value,
+                            _ => unreachable!(),
+                        })
+                        .collect::<Vec<_>>();
+
+                    let list_start = result.pop().unwrap();
+                    let list = Value::List(Rc::new(items));
+
+                    result.push(Token {
+                        lexeme: list.to_string(),
+                        typ3: TokenType::Value(list),
+                        location: list_start.location,
+                    });
+                }
+                _ => result.push(token),
+            }
+        }
+
+        result
+    }
+
+    /// Collects struct and extern declarations and resolves the words they define,
+    /// `struct Point { x y }` defines 'Point', 'Point.x@', 'Point.x!', 'Point.y@' and 'Point.y!',
+    /// `extern name` defines 'name' as a call to the native function registered as 'name'
+    fn resolve_words(
+        &self,
+        tokens: Vec<Token>,
+        byte_code: &mut ByteCode,
+    ) -> CompileResult<Vec<Token>> {
+        let mut words = HashMap::new();
+        let mut remaining = vec![];
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if matches!(&tokens[i].typ3, TokenType::Word(word) if word == "extern") {
+                let name = match &tokens[i + 1].typ3 {
+                    TokenType::Word(name) if !name.contains('.') => name.clone(),
+                    _ => {
+                        return Err(Error::CompileError(
+                            "expected a native function name after 'extern'".to_string(),
+                            tokens[i + 1].location.clone(),
+                        ));
+                    }
+                };
+
+                if words.contains_key(&name) {
+                    return Err(Error::CompileError(
+                        format!("'{name}' is already defined"),
+                        tokens[i + 1].location.clone(),
+                    ));
+                }
+
+                // the vm links the names to the registered functions before running
+                let index = byte_code.natives.len() as isize;
+                words.insert(name.clone(), OpCode::CallNative(index));
+                byte_code.natives.push(name);
+
+                i += 2;
+                continue;
+            }
+
+            if !matches!(&tokens[i].typ3, TokenType::Word(word) if word == "struct") {
+                remaining.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let name = match &tokens[i + 1].typ3 {
+                TokenType::Word(name) if !name.contains('.') && name != "struct" => name.clone(),
+                _ => {
+                    return Err(Error::CompileError(
+                        "expected a struct name after 'struct'".to_string(),
+                        tokens[i + 1].location.clone(),
+                    ));
+                }
+            };
+
+            if !matches!(tokens[i + 2].typ3, TokenType::Instruction(OpCode::LBrace)) {
+                return Err(Error::CompileError(
+                    format!("expected '{{' after 'struct {name}'"),
+                    tokens[i + 2].location.clone(),
+                ));
+            }
+
+            if words.contains_key(&name) {
+                return Err(Error::CompileError(
+                    format!("struct '{name}' is already defined"),
+                    tokens[i + 1].location.clone(),
+                ));
+            }
+
+            let mut fields: Vec<String> = vec![];
+            let mut j = i + 3;
+
+            loop {
+                match &tokens[j].typ3 {
+                    TokenType::Instruction(OpCode::RBrace(..)) => break,
+                    TokenType::Word(field) if !field.contains('.') => {
+                        if fields.contains(field) {
+                            return Err(Error::CompileError(
+                                format!("field '{field}' is already defined in struct '{name}'"),
+                                tokens[j].location.clone(),
+                            ));
+                        }
+
+                        fields.push(field.clone());
+                    }
+                    _ => {
+                        return Err(Error::CompileError(
+                            format!("expected a field name or '}}' in struct '{name}'"),
+                            tokens[j].location.clone(),
+                        ));
+                    }
+                }
+
+                j += 1;
+            }
+
+            // field offsets are resolved here so the vm never looks up names
+            let type_id = byte_code.structs.len() as isize;
+            words.insert(name.clone(), OpCode::StructNew(type_id));
+
+            for (offset, field) in fields.iter().enumerate() {
+                let get = OpCode::StructGet(type_id, offset as isize);
+                let set = OpCode::StructSet(type_id, offset as isize);
+                words.insert(format!("{name}.{field}@"), get);
+                words.insert(format!("{name}.{field}!"), set);
+            }
+
+            byte_code.structs.push(Rc::new(StructDef { name, fields }));
+            i = j + 1;
+        }
+
+        for token in remaining.iter_mut() {
+            if let TokenType::Word(word) = &token.typ3 {
+                match words.get(word) {
+                    Some(opcode) => token.typ3 = TokenType::Instruction(*opcode),
+                    None => {
+                        return Err(Error::CompileError(
+                            format!("unknown identifier '{word}'"),
+                            token.location.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(remaining)
+    }
+
+    fn preprocess_program(&self, tokens: &mut [Token]) -> CompileResult<()> {
+        let mut blocks = vec![];
+        let mut loops: Vec<Loop> = vec![];
+        let mut pending: Option<Block> = None;
+        let mut pending_label = None;
+        let mut closed_if: Option<usize> = None;
+        let mut closed_try: Option<usize> = None;
+        let mut lists = vec![];
+        let mut count = 0;
+        let mut ip = 0;
+
+        loop {
+            let is_lbrace = matches!(tokens[count].typ3, TokenType::Instruction(OpCode::LBrace));
+            let is_loop = matches!(
+                tokens[count].typ3,
+                TokenType::Instruction(OpCode::While | OpCode::For(..) | OpCode::ForStep(..))
+            );
+
+            // an 'if', 'else', 'do' or 'for' that isn't followed by a block is left
+            // unresolved so that the cross reference check can report it
+            if !is_lbrace {
+                pending = None;
+            }
+
+            if pending_label.is_some() && !is_loop {
+                return Err(Error::CompileError(
+                    "loop labels can only be applied to loops".to_string(),
+                    tokens[count - 1].location.clone(),
+                ));
+            }
+
+            let is_catch = matches!(
+                tokens[count].typ3,
+                TokenType::Instruction(OpCode::Catch(..))
+            );
+            if let (Some(try_index), false) = (closed_try, is_catch) {
+                return Err(Error::CompileError(
+                    "'try' block must be followed by a 'catch' block".to_string(),
+                    tokens[try_index].location.clone(),
+                ));
+            }
+
+            let mut just_closed_if = None;
+            let mut just_closed_try = None;
+
+            match &tokens[count].typ3 {
+                TokenType::Instruction(opcode) => match opcode {
+                    OpCode::Push
+                    | OpCode::Dup
+                    | OpCode::Add
+                    | OpCode::Sub
+                    | OpCode::Mul
+                    | OpCode::Div
+                    | OpCode::Lt
+                    | OpCode::Lte
+                    | OpCode::Gt
+                    | OpCode::Gte
+                    | OpCode::Eq
+                    | OpCode::Ne
+                    | OpCode::Dump
+                    | OpCode::Halt
+                    | OpCode::I
+                    | OpCode::J
+                    | OpCode::Len
+                    | OpCode::Slice
+                    | OpCode::IndexOf
+                    | OpCode::Split
+                    | OpCode::Join
+                    | OpCode::Upper
+                    | OpCode::Lower
+                    | OpCode::Trim
+                    | OpCode::CharAt
+                    | OpCode::ToInt
+                    | OpCode::ToStr
+                    | OpCode::Fmt
+                    | OpCode::ListPush
+                    | OpCode::ListPop
+                    | OpCode::ListGet
+                    | OpCode::ListSet
+                    | OpCode::MapNew
+                    | OpCode::MapInsert
+                    | OpCode::MapGetOr
+                    | OpCode::MapRemove
+                    | OpCode::MapContains
+                    | OpCode::MapKeys
+                    | OpCode::Call
+                    | OpCode::Times
+                    | OpCode::Each
+                    | OpCode::MapList
+                    | OpCode::Throw
+                    | OpCode::Exit
+                    | OpCode::ReadLine
+                    | OpCode::ReadAll
+                    | OpCode::ReadInt
+                    | OpCode::Argc
+                    | OpCode::Argv
+                    | OpCode::GetEnv
+                    | OpCode::Open
+                    | OpCode::ReadFile
+                    | OpCode::WriteFile
+                    | OpCode::AppendFile
+                    | OpCode::Exists
+                    | OpCode::Delete => {
+                        ip += 1;
+                    }
+                    OpCode::ListStart => {
+                        // a list has to be closed inside of the block it was opened in
+                        lists.push((count, blocks.len()));
+                        ip += 1;
+                    }
+                    OpCode::ListEnd => {
+                        match lists.pop() {
+                            Some((_, depth)) if depth == blocks.len() => (),
+                            Some(..) => {
+                                return Err(Error::CompileError(
+                                    "']' can not close a list opened outside of this block"
+                                        .to_string(),
+                                    tokens[count].location.clone(),
+                                ));
+                            }
+                            None => {
+                                return Err(Error::CompileError(
+                                    "']' does not close any list".to_string(),
+                                    tokens[count].location.clone(),
+                                ));
+                            }
+                        }
+
+                        ip += 1;
+                    }
+                    OpCode::If(..) => {
+                        pending = Some(Block::If(count));
+                        ip += 9;
+                    }
+                    OpCode::Else(..) => {
+                        let Some(if_index) = closed_if else {
+                            return Err(Error::CompileError(
+                                "'else' can only close if blocks".to_string(),
+                                tokens[count].location.clone(),
+                            ));
+                        };
+
+                        ip += 9;
+                        // a false condition skips over the 'else' jump into the else block
+                        tokens[if_index].typ3 = TokenType::Instruction(OpCode::If(ip as isize));
+                        pending = Some(Block::Else(count));
+                    }
+                    OpCode::While => {
+                        loops.push(Loop {
+                            label: pending_label.take(),
+                            counted: false,
+                            condition_ip: ip,
+                            entry_index: None,
+                            lists: lists.len(),
+                            breaks: vec![],
+                            continues: vec![],
+                        });
+                        ip += 1;
+                    }
+                    OpCode::Next(..) => {
+                        // only ever produced by closing a counted loop
+                        ip += 9;
+                    }
+                    OpCode::StructNew(..)
+                    | OpCode::Assert(..)
+                    | OpCode::AssertEq(..)
+                    | OpCode::CallNative(..) => {
+                        ip += 9;
+                    }
+                    OpCode::StructGet(..) | OpCode::StructSet(..) => {
+                        ip += 17;
+                    }
+                    OpCode::For(..) | OpCode::ForStep(..) => {
+                        loops.push(Loop {
+                            label: pending_label.take(),
+                            counted: true,
+                            condition_ip: ip,
+                            entry_index: Some(count),
+                            lists: lists.len(),
+                            breaks: vec![],
+                            continues: vec![],
+                        });
+                        pending = Some(Block::Loop);
+                        ip += 9;
+                    }
+                    OpCode::Do(..) => {
+                        match loops.last_mut() {
+                            Some(lp) if !lp.counted && lp.entry_index.is_none() => {
+                                lp.entry_index = Some(count)
+                            }
+                            _ => {
+                                return Err(Error::CompileError(
+                                    "'do' must follow a 'while' condition".to_string(),
+                                    tokens[count].location.clone(),
+                                ));
+                            }
+                        }
+
+                        pending = Some(Block::Loop);
+                        ip += 9;
+                    }
+                    OpCode::Try(..) => {
+                        pending = Some(Block::Try(count, loops.len()));
+                        ip += 9;
+                    }
+                    OpCode::Catch(..) => {
+                        let Some(try_index) = closed_try else {
+                            return Err(Error::CompileError(
+                                "'catch' can only follow a try block".to_string(),
+                                tokens[count].location.clone(),
+                            ));
+                        };
+
+                        ip += 9;
+                        // a throw inside of the try block lands at the start of the catch block
+                        tokens[try_index].typ3 = TokenType::Instruction(OpCode::Try(ip as isize));
+                        pending = Some(Block::Catch(count));
+                    }
+                    OpCode::Quote(..) => {
+                        blocks.push(Block::Quote(count, loops.len()));
+                        ip += 9;
+                    }
+                    OpCode::Return => {
+                        check_lists_closed(&lists, blocks.len(), tokens)?;
+
+                        let Some(Block::Quote(quote_index, ..)) = blocks.pop() else {
+                            return Err(Error::CompileError(
+                                "':]' can only close a quotation, expected '}'".to_string(),
+                                tokens[count].location.clone(),
+                            ));
+                        };
+
+                        ip += 1;
+                        tokens[quote_index].typ3 =
+                            TokenType::Instruction(OpCode::Quote(ip as isize));
+                    }
+                    OpCode::LBrace => {
+                        blocks.push(pending.take().unwrap_or(Block::Plain));
+                        ip += 1;
+                    }
+                    OpCode::RBrace(..) => {
+                        check_lists_closed(&lists, blocks.len(), tokens)?;
+
+                        let Some(block) = blocks.pop() else {
+                            return Err(Error::CompileError(
+                                "'}' does not close any block".to_string(),
+                                tokens[count].location.clone(),
+                            ));
+                        };
+
+                        let rbrace_ip = ip;
+                        ip += 9;
+
+                        match block {
+                            Block::If(if_index) => {
+                                tokens[if_index].typ3 =
+                                    TokenType::Instruction(OpCode::If(ip as isize));
+                                just_closed_if = Some(if_index);
+                            }
+                            Block::Else(else_index) => {
+                                tokens[else_index].typ3 =
+                                    TokenType::Instruction(OpCode::Else(ip as isize));
+                            }
+                            Block::Loop => {
+                                let lp = loops.pop().unwrap();
+                                let entry_index = lp.entry_index.unwrap();
+
+                                if lp.counted {
+                                    // the counted loop body starts right after the 'for'
+                                    let body_ip = lp.condition_ip + 9;
+                                    tokens[count].typ3 =
+                                        TokenType::Instruction(OpCode::Next(body_ip as isize));
+                                    tokens[entry_index].typ3 = match tokens[entry_index].typ3 {
+                                        TokenType::Instruction(OpCode::ForStep(..)) => {
+                                            TokenType::Instruction(OpCode::ForStep(ip as isize))
+                                        }
+                                        _ => TokenType::Instruction(OpCode::For(ip as isize)),
+                                    };
+                                } else {
+                                    tokens[count].typ3 = TokenType::Instruction(OpCode::RBrace(
+                                        lp.condition_ip as isize,
+                                    ));
+                                    tokens[entry_index].typ3 =
+                                        TokenType::Instruction(OpCode::Do(ip as isize));
+                                }
+
+                                for (break_index, unloop) in lp.breaks {
+                                    tokens[break_index].typ3 =
+                                        TokenType::Instruction(OpCode::Break(ip as isize, unloop));
+                                }
+
+                                // continuing a counted loop has to go through 'Next'
+                                for (continue_index, unloop) in lp.continues {
+                                    tokens[continue_index].typ3 = TokenType::Instruction(
+                                        OpCode::Continue(rbrace_ip as isize, unloop),
+                                    );
+                                }
+                            }
+                            Block::Try(try_index, _) => {
+                                just_closed_try = Some(try_index);
+                            }
+                            Block::Catch(catch_index) => {
+                                tokens[catch_index].typ3 =
+                                    TokenType::Instruction(OpCode::Catch(ip as isize));
+                            }
+                            Block::Plain => (),
+                            Block::Quote(..) => {
+                                return Err(Error::CompileError(
+                                    "'}' can not close a quotation, expected ':]'".to_string(),
+                                    tokens[count].location.clone(),
+                                ));
+                            }
+                        }
+                    }
+                    OpCode::Break(..) | OpCode::Continue(..) => {
+                        let opcode = *opcode;
+                        let label = match &tokens[count + 1].typ3 {
+                            TokenType::Label(name) => Some(name.clone()),
+                            _ => None,
+                        };
+
+                        // loops outside of the current quotation or try block are out of reach
+                        let barrier = blocks.iter().rev().find_map(|block| match block {
+                            Block::Quote(_, loop_count) => Some(("a quotation", *loop_count)),
+                            Block::Try(_, loop_count) => Some(("a 'try' block", *loop_count)),
+                            _ => None,
+                        });
+
+                        let position = loops.iter().rposition(|lp| {
+                            lp.entry_index.is_some() && (label.is_none() || lp.label == label)
+                        });
+
+                        let keyword = &tokens[count].lexeme;
+                        let err = match (position, barrier) {
+                            (Some(position), Some((block, loop_count)))
+                                if position < loop_count =>
+                            {
+                                Some(format!("'{keyword}' can not jump out of {block}"))
+                            }
+                            (Some(..), _) => None,
+                            (None, _) => Some(match &label {
+                                Some(name) => format!("use of undeclared loop label '{name}'"),
+                                None => format!("'{keyword}' can only be used inside of a loop"),
+                            }),
+                        };
+
+                        if let Some(err) = err {
+                            return Err(Error::CompileError(err, tokens[count].location.clone()));
+                        }
+
+                        let position = position.unwrap();
+
+                        if lists.len() > loops[position].lists {
+                            return Err(Error::CompileError(
+                                format!("'{keyword}' can not jump out of a list literal"),
+                                tokens[count].location.clone(),
+                            ));
+                        }
+
+                        // the number of counted loop frames the jump leaves behind
+                        let inner_counted =
+                            loops[position + 1..].iter().filter(|lp| lp.counted).count() as isize;
+
+                        let lp = &mut loops[position];
+
+                        if let OpCode::Break(..) = opcode {
+                            let unloop = inner_counted + lp.counted as isize;
+                            lp.breaks.push((count, unloop));
+                        } else if lp.counted {
+                            lp.continues.push((count, inner_counted));
+                        } else {
+                            tokens[count].typ3 = TokenType::Instruction(OpCode::Continue(
+                                lp.condition_ip as isize,
+                                inner_counted,
+                            ));
+                        }
+
+                        ip += 17;
+
+                        // the label belongs to this instruction so skip over it
+                        if label.is_some() {
+                            count += 1;
+                        }
+                    }
+                },
+                TokenType::Value(..) => {
+                    ip += 9;
+                }
+                TokenType::Label(name) => {
+                    pending_label = Some(name.clone());
+                }
+                TokenType::Word(..) | TokenType::Error => (),
+                TokenType::Eof => {
+                    if let Some((list_index, _)) = lists.pop() {
+                        return Err(Error::CompileError(
+                            "unterminated list literal, expected ']'".to_string(),
+                            tokens[list_index].location.clone(),
+                        ));
+                    }
+
+                    let quote = blocks.iter().rev().find_map(|block| match block {
+                        Block::Quote(quote_index, _) => Some(*quote_index),
+                        _ => None,
+                    });
+                    if let Some(quote_index) = quote {
+                        return Err(Error::CompileError(
+                            "unterminated quotation, expected ':]'".to_string(),
+                            tokens[quote_index].location.clone(),
+                        ));
+                    }
+
+                    break;
+                }
+            }
+
+            closed_if = just_closed_if;
+            closed_try = just_closed_try;
+            count += 1;
+        }
+
+        Ok(())
+    }
+
+    fn verify_cross_reference_blocks(&self, tokens: &[Token]) -> CompileResult<()> {
+        for token in tokens.iter() {
+            match token.typ3 {
+                TokenType::Instruction(opcode) => match opcode {
+                    OpCode::If(return_addr) => {
+                        if return_addr < 0 {
+                            return Err(Error::CompileError(
+                                format!(
+                                    "invalid return address '{return_addr}',
+block was not referenced with end instruction pointer
+-----------------------------------
+to fix this use '{{' and '}}' to allow the compiler to detect the end of the block"
+                                ),
+                                token.location.clone(),
+                            ));
+                        }
+                    }
+                    OpCode::Else(return_addr) => {
+                        if return_addr < 0 {
+                            return Err(Error::CompileError(
+                                format!(
+                                    "invalid return address '{return_addr}'
+block was not referenced with end instruction pointer
+-----------------------------------
+to fix this use '{{' and '}}' to allow the compiler to detect the end of the block"
+                                ),
+                                token.location.clone(),
+                            ));
+                        }
+                    }
+                    OpCode::Do(return_addr)
+                    | OpCode::For(return_addr)
+                    | OpCode::ForStep(return_addr)
+                    | OpCode::Try(return_addr)
+                    | OpCode::Catch(return_addr) => {
+                        if return_addr < 0 {
+                            return Err(Error::CompileError(
+                                format!(
+                                    "invalid return address '{return_addr}'
+block was not referenced with end instruction pointer
+-----------------------------------
+to fix this use '{{' and '}}' to allow the compiler to detect the end of the block"
+                                ),
+                                token.location.clone(),
+                            ));
+                        }
+                    }
+                    _ => continue,
+                },
+                _ => continue,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a format string known at compile time has enough values
+    /// below it. The stack depth is followed into 'if', 'else', 'while',
+    /// 'for-step', 'try' and 'catch' blocks and kept after them when every
+    /// path leaves the same depth. It is unknown at the start of quotations,
+    /// 'for' loops (which take either a list or two ints) and after words or
+    /// instructions that change the stack by an amount known only at runtime,
+    /// where format strings are left to be checked when they run.
+    fn verify_format_strings(&self, tokens: &[Token]) -> CompileResult<()> {
+        let mut depth = Some(0);
+        let mut blocks: Vec<DepthBlock> = vec![];
+        let mut opening = None;
+        let mut closed: Option<(Option<usize>, Option<usize>)> = None;
+
+        let pop = |depth: Option<usize>, count: usize| depth.and_then(|d| d.checked_sub(count));
+        let merge = |lhs: Option<usize>, rhs: Option<usize>| if lhs == rhs { lhs } else { None };
+        let block = |skip, next| DepthBlock {
+            skip,
+            next,
+            jumps: false,
+            quotation: false,
+        };
+
+        for (i, token) in tokens.iter().enumerate() {
+            let effect = match &token.typ3 {
+                TokenType::Instruction(OpCode::If(..)) => {
+                    depth = pop(depth, 1);
+                    opening = Some(block(depth, depth));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Do(..)) => {
+                    depth = pop(depth, 1);
+                    opening = Some(block(depth, None));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::For(..)) => {
+                    depth = None;
+                    opening = Some(block(None, None));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::ForStep(..)) => {
+                    depth = pop(depth, 3);
+                    opening = Some(block(depth, None));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Try(..)) => {
+                    // a throw unwinds to the depth of the try and pushes its value
+                    opening = Some(block(None, depth.map(|d| d + 1)));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Else(..) | OpCode::Catch(..)) => {
+                    // starts where the block before it started and merges with its end
+                    let (next, end) = closed.take().unwrap_or((None, None));
+                    depth = next;
+                    opening = Some(block(end, None));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::LBrace) => {
+                    blocks.push(opening.take().unwrap_or(block(None, None)));
+                    continue;
+                }
+                TokenType::Instruction(OpCode::RBrace(..)) => {
+                    if let Some(block) = blocks.pop() {
+                        let end = if block.jumps { None } else { depth };
+                        closed = Some((block.next, end));
+                        depth = merge(end, block.skip);
+                    }
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Quote(..)) => {
+                    blocks.push(DepthBlock {
+                        quotation: true,
+                        ..block(depth.map(|d| d + 1), None)
+                    });
+                    depth = None;
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Return) => {
+                    if let Some(block) = blocks.pop().filter(|block| block.quotation) {
+                        depth = block.skip;
+                    }
+                    continue;
+                }
+                TokenType::Instruction(OpCode::Break(..) | OpCode::Continue(..)) => {
+                    for block in blocks.iter_mut() {
+                        block.jumps = true;
+                    }
+                    continue;
+                }
+                TokenType::Instruction(OpCode::While) => Some((0, 0)),
+                TokenType::Instruction(OpCode::Fmt) => {
+                    let format = match i.checked_sub(1).map(|i| &tokens[i]) {
+                        Some(Token {
+                            typ3: TokenType::Value(Value::String(format)),
+                            location,
+                            ..
+                        }) => Some(
+                            Format::parse(format)
+                                .map_err(|err| Error::CompileError(err, location.clone()))?,
+                        ),
+                        _ => None,
+                    };
+
+                    match (format, depth) {
+                        (Some(format), Some(depth)) => {
+                            let count = format.placeholder_count();
+                            // the format string itself is on top of the stack
+                            let available = depth - 1;
+
+                            if available < count {
+                                return Err(Error::CompileError(
+                                    format!(
+                                        "format string expects {count} values but the stack only holds {available}"
+                                    ),
+                                    token.location.clone(),
+                                ));
+                            }
+
+                            Some((count + 1, 1))
+                        }
+                        (Some(format), None) => Some((format.placeholder_count() + 1, 1)),
+                        (None, _) => None,
+                    }
+                }
+                TokenType::Instruction(opcode) => Compiler::stack_effect(opcode),
+                TokenType::Value(..) => Some((0, 1)),
+                TokenType::Word(..) => None,
+                TokenType::Label(..) | TokenType::Error | TokenType::Eof => Some((0, 0)),
+            };
+
+            depth = match (depth, effect) {
+                (Some(depth), Some((pops, pushes))) if depth >= pops => Some(depth - pops + pushes),
+                _ => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    fn stack_effect(opcode: &OpCode) -> Option<(usize, usize)> {
+        match opcode {
+            OpCode::Push
+            | OpCode::I
+            | OpCode::J
+            | OpCode::MapNew
+            | OpCode::Quote(..)
+            | OpCode::Argc => Some((0, 1)),
+            OpCode::Dup => Some((1, 2)),
+            OpCode::Dump | OpCode::Assert(..) | OpCode::Delete => Some((1, 0)),
+            OpCode::AssertEq(..) | OpCode::WriteFile | OpCode::AppendFile => Some((2, 0)),
+            OpCode::Len
+            | OpCode::Upper
+            | OpCode::Lower
+            | OpCode::Trim
+            | OpCode::ToInt
+            | OpCode::ToStr
+            | OpCode::MapKeys
+            | OpCode::StructGet(..)
+            | OpCode::Argv
+            | OpCode::Open
+            | OpCode::ReadFile
+            | OpCode::Exists => Some((1, 1)),
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Lt
+            | OpCode::Lte
+            | OpCode::Gt
+            | OpCode::Gte
+            | OpCode::Eq
+            | OpCode::Ne
+            | OpCode::IndexOf
+            | OpCode::CharAt
+            | OpCode::ListPush
+            | OpCode::ListGet
+            | OpCode::MapRemove
+            | OpCode::MapContains
+            | OpCode::StructSet(..) => Some((2, 1)),
+            OpCode::ListPop => Some((1, 2)),
+            OpCode::ReadLine | OpCode::ReadAll | OpCode::ReadInt => Some((0, 2)),
+            OpCode::GetEnv => Some((1, 2)),
+            OpCode::Slice | OpCode::ListSet | OpCode::MapInsert | OpCode::MapGetOr => Some((3, 1)),
+            _ => None,
+        }
+    }
+
+    fn bytes_from_token(&self, byte_code: &mut ByteCode, token: &Token) {
+        match &token.typ3 {
+            TokenType::Instruction(opcode) => match opcode {
+                OpCode::If(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Else(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Do(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::RBrace(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Break(return_addr, unloop) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                    let bytes: [u8; 8] = unloop.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Continue(return_addr, unloop) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                    let bytes: [u8; 8] = unloop.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::For(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::ForStep(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Next(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Try(return_addr) | OpCode::Catch(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Quote(return_addr) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = return_addr.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::Assert(..) | OpCode::AssertEq(..) => {
+                    // the source location is kept as a constant for the failure message
+                    byte_code.bytes.push(opcode.as_byte());
+                    byte_code
+                        .constants
+                        .push(Value::String(token.location.to_string()));
+                    let constant_index = byte_code.constants.len() - 1;
+                    let bytes: [u8; 8] = constant_index.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::CallNative(index) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = index.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::StructNew(type_id) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = type_id.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                OpCode::StructGet(type_id, field) | OpCode::StructSet(type_id, field) => {
+                    byte_code.bytes.push(opcode.as_byte());
+                    let bytes: [u8; 8] = type_id.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                    let bytes: [u8; 8] = field.to_ne_bytes();
+                    byte_code.bytes.extend_from_slice(&bytes);
+                }
+                _ => {
+                    byte_code.bytes.push(opcode.as_byte());
+                }
+            },
+            TokenType::Value(value) => {
+                byte_code.bytes.push(OpCode::Push.as_byte());
+                byte_code.constants.push(value.clone());
+                let constant_index = byte_code.constants.len() - 1;
+                let bytes: [u8; 8] = constant_index.to_ne_bytes();
+                byte_code.bytes.extend_from_slice(&bytes);
+            }
+            TokenType::Label(..) => (),
+            TokenType::Word(..) | TokenType::Error => unreachable!(),
+            TokenType::Eof => {
+                byte_code.bytes.push(OpCode::Halt.as_byte());
+                let halt_index = byte_code.bytes.len() - 1;
+                let bytes: [u8; 8] = halt_index.to_ne_bytes();
+                byte_code.bytes.splice(0..0, bytes);
+            }
+        }
+    }
+
+    fn constants_to_bytes(&self, constants: &[Value]) -> Vec<u8> {
+        let mut result = vec![];
+
+        let count_as_bytes: [u8; 8] = constants.len().to_ne_bytes();
+        result.extend_from_slice(&count_as_bytes);
+
+        for constant in constants.iter() {
+            self.constant_to_bytes(constant, &mut result);
+        }
+
+        result
+    }
+
+    fn constant_to_bytes(&self, constant: &Value, result: &mut Vec<u8>) {
+        match constant {
+            Value::Int32(int32) => {
+                result.push(constant.constant_type());
+                let bytes: [u8; 4] = int32.to_ne_bytes();
+                result.extend_from_slice(&bytes);
+            }
+            Value::UInt32(uint32) => {
+                result.push(constant.constant_type());
+                let bytes: [u8; 4] = uint32.to_ne_bytes();
+                result.extend_from_slice(&bytes);
+            }
+            Value::String(string) => {
+                result.push(constant.constant_type());
+                let len = string.len();
+                let len_as_bytes: [u8; 8] = len.to_ne_bytes();
+                result.extend_from_slice(&len_as_bytes);
+                result.extend_from_slice(string.as_bytes());
+            }
+            Value::List(items) => {
+                result.push(constant.constant_type());
+                let len = items.len();
+                let len_as_bytes: [u8; 8] = len.to_ne_bytes();
+                result.extend_from_slice(&len_as_bytes);
+
+                for item in items.iter() {
+                    self.constant_to_bytes(item, result);
+                }
+            }
+            // these have no literal syntax so they never end up in the constant pool
+            Value::Map(..) | Value::Struct(..) | Value::Quotation(..) | Value::File(..) => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn natives_to_bytes(&self, natives: &[String]) -> Vec<u8> {
+        let mut result = vec![];
+
+        let count_as_bytes: [u8; 8] = natives.len().to_ne_bytes();
+        result.extend_from_slice(&count_as_bytes);
+
+        for name in natives.iter() {
+            let len_as_bytes: [u8; 8] = name.len().to_ne_bytes();
+            result.extend_from_slice(&len_as_bytes);
+            result.extend_from_slice(name.as_bytes());
+        }
+
+        result
+    }
+
+    /// The source paths come first so that every line entry
+    /// can refer to its path by index
+    fn debug_info_to_bytes(&self, debug_info: &DebugInfo) -> Vec<u8> {
+        let mut result = vec![];
+        let mut paths: Vec<&str> = vec![];
+
+        for entry in debug_info.lines.iter() {
+            if !paths.contains(&entry.location.path.as_str()) {
+                paths.push(&entry.location.path);
+            }
+        }
+
+        let count_as_bytes: [u8; 8] = paths.len().to_ne_bytes();
+        result.extend_from_slice(&count_as_bytes);
+
+        for path in paths.iter() {
+            let len_as_bytes: [u8; 8] = path.len().to_ne_bytes();
+            result.extend_from_slice(&len_as_bytes);
+            result.extend_from_slice(path.as_bytes());
+        }
+
+        let count_as_bytes: [u8; 8] = debug_info.lines.len().to_ne_bytes();
+        result.extend_from_slice(&count_as_bytes);
+
+        for entry in debug_info.lines.iter() {
+            let path_index = paths
+                .iter()
+                .position(|path| *path == entry.location.path)
+                .unwrap();
+
+            for value in [
+                entry.offset,
+                path_index,
+                entry.location.line,
+                entry.location.column,
+            ] {
+                let bytes: [u8; 8] = value.to_ne_bytes();
+                result.extend_from_slice(&bytes);
+            }
+
+            let len_as_bytes: [u8; 8] = entry.symbol.len().to_ne_bytes();
+            result.extend_from_slice(&len_as_bytes);
+            result.extend_from_slice(entry.symbol.as_bytes());
+        }
+
+        result
+    }
+
+    fn structs_to_bytes(&self, structs: &[Rc<StructDef>]) -> Vec<u8> {
+        let mut result = vec![];
+
+        let count_as_bytes: [u8; 8] = structs.len().to_ne_bytes();
+        result.extend_from_slice(&count_as_bytes);
+
+        for def in structs.iter() {
+            let field_count_as_bytes: [u8; 8] = def.fields.len().to_ne_bytes();
+            result.extend_from_slice(&field_count_as_bytes);
+
+            // the struct name followed by its field names
+            for name in std::iter::once(&def.name).chain(def.fields.iter()) {
+                let len_as_bytes: [u8; 8] = name.len().to_ne_bytes();
+                result.extend_from_slice(&len_as_bytes);
+                result.extend_from_slice(name.as_bytes());
+            }
+        }
+
+        result
+    }
+}
+
+/// Fails when a list literal opened inside of the block that is being
+/// closed is still open, `depth` is the number of open blocks
+fn check_lists_closed(
+    lists: &[(usize, usize)],
+    depth: usize,
+    tokens: &[Token],
+) -> CompileResult<()> {
+    match lists.last() {
+        Some((list_index, list_depth)) if *list_depth >= depth => Err(Error::CompileError(
+            "unterminated list literal, expected ']' before the end of the block".to_string(),
+            tokens[*list_index].location.clone(),
+        )),
+        _ => Ok(()),
+    }
+}
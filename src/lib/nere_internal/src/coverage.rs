@@ -0,0 +1,184 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ByteCode, Location, OpCode};
+
+/// Which instructions and branches a program ran, recorded by the vm
+/// when `RuntimeOptions::coverage` is set
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    /// How often each instruction ran, keyed by offset
+    pub hits: BTreeMap<usize, u64>,
+    /// How often each 'if' was entered and skipped, keyed by offset
+    pub branches: BTreeMap<usize, (u64, u64)>,
+}
+
+impl Coverage {
+    pub fn record(&mut self, ip: usize) {
+        *self.hits.entry(ip).or_default() += 1;
+    }
+
+    pub fn record_branch(&mut self, ip: usize, entered: bool) {
+        let (taken, skipped) = self.branches.entry(ip).or_default();
+
+        match entered {
+            true => *taken += 1,
+            false => *skipped += 1,
+        }
+    }
+}
+
+/// The coverage of every instruction in a program, including the ones
+/// that never ran, this is what 'nere --coverage' writes out
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub instructions: Vec<InstructionCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstructionCoverage {
+    pub offset: usize,
+    pub hits: u64,
+    /// None for stripped binaries
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchCoverage {
+    pub offset: usize,
+    /// The number of times the 'if' block was entered
+    pub taken: u64,
+    /// The number of times the 'if' block was jumped over
+    pub skipped: u64,
+    pub location: Option<Location>,
+}
+
+/// What ran on a single source line
+#[derive(Debug, Default, Clone)]
+pub struct LineCoverage {
+    /// The hits of the line's most executed instruction
+    pub hits: u64,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl CoverageReport {
+    pub fn new(coverage: &Coverage, byte_code: &ByteCode) -> Self {
+        let mut report = CoverageReport::default();
+        let location = |offset| {
+            byte_code
+                .debug_info
+                .as_ref()
+                .and_then(|debug_info| debug_info.entry_at(offset))
+                .map(|entry| entry.location.clone())
+        };
+
+        let mut next = 0;
+
+        while next < byte_code.bytes.len() {
+            let offset = next;
+            let opcode = OpCode::from(byte_code.bytes[offset]);
+            next += opcode.instruction_size();
+
+            // the compiler ends every program with a halt that isn't in the source
+            if opcode == OpCode::Halt {
+                continue;
+            }
+
+            report.instructions.push(InstructionCoverage {
+                offset,
+                hits: coverage.hits.get(&offset).copied().unwrap_or_default(),
+                location: location(offset),
+            });
+
+            if let OpCode::If(..) = opcode {
+                let (taken, skipped) = coverage.branches.get(&offset).copied().unwrap_or_default();
+
+                report.branches.push(BranchCoverage {
+                    offset,
+                    taken,
+                    skipped,
+                    location: location(offset),
+                });
+            }
+        }
+
+        report
+    }
+
+    /// The lines of every source file that instructions were compiled from,
+    /// keyed by path and line number, empty for stripped binaries
+    pub fn lines(&self) -> BTreeMap<String, BTreeMap<usize, LineCoverage>> {
+        let mut files: BTreeMap<String, BTreeMap<usize, LineCoverage>> = BTreeMap::new();
+
+        for instruction in self.instructions.iter() {
+            if let Some(loc) = &instruction.location {
+                let line = files
+                    .entry(loc.path.clone())
+                    .or_default()
+                    .entry(loc.line)
+                    .or_default();
+
+                line.hits = line.hits.max(instruction.hits);
+            }
+        }
+
+        for branch in self.branches.iter() {
+            if let Some(loc) = &branch.location {
+                files
+                    .entry(loc.path.clone())
+                    .or_default()
+                    .entry(loc.line)
+                    .or_default()
+                    .branches
+                    .push(branch.clone());
+            }
+        }
+
+        files
+    }
+
+    /// The report as lcov records, every 'if' is a block with two
+    /// branches, entering it and skipping it
+    pub fn lcov(&self) -> String {
+        let mut lcov = String::new();
+
+        for (path, lines) in self.lines().iter() {
+            writeln!(lcov, "TN:").unwrap();
+            writeln!(lcov, "SF:{path}").unwrap();
+
+            let mut branches_found = 0;
+            let mut branches_hit = 0;
+
+            for (number, line) in lines.iter() {
+                for branch in line.branches.iter() {
+                    for (index, count) in [branch.taken, branch.skipped].into_iter().enumerate() {
+                        let taken = match branch.taken + branch.skipped {
+                            0 => "-".to_string(),
+                            _ => count.to_string(),
+                        };
+
+                        writeln!(lcov, "BRDA:{number},{},{index},{taken}", branch.offset).unwrap();
+                        branches_found += 1;
+                        branches_hit += (count > 0) as usize;
+                    }
+                }
+            }
+
+            writeln!(lcov, "BRF:{branches_found}").unwrap();
+            writeln!(lcov, "BRH:{branches_hit}").unwrap();
+
+            for (number, line) in lines.iter() {
+                writeln!(lcov, "DA:{number},{}", line.hits).unwrap();
+            }
+
+            writeln!(lcov, "LF:{}", lines.len()).unwrap();
+            let hit = lines.values().filter(|line| line.hits > 0).count();
+            writeln!(lcov, "LH:{hit}").unwrap();
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+
+        lcov
+    }
+}
@@ -0,0 +1,253 @@
+//! An interactive debugger that steps through a program with commands read
+//! from its input, used by 'nere debug'
+
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    io::{BufRead, Write},
+};
+
+use crate::{
+    disassembler::Disassembler,
+    virtual_machine::{DebugAction, DebugHook, VirtualMachine},
+    OpCode,
+};
+
+const PROMPT: &str = "(ndb) ";
+const HELP: &str = "\
+commands:
+  step, s              run the next instruction
+  next, n              run the next instruction, stepping over calls
+  continue, c          run until the next breakpoint
+  break, b <offset>    stop before the instruction at <offset>
+  break line <n>       stop before the first instruction of line <n>
+  delete, d <offset>   remove the breakpoint at <offset>
+  breakpoints, info    list the breakpoints
+  stack, st            print the operand stack
+  print, p <index>     print a constant
+  list, l [n]          disassemble the next n instructions
+  quit, q              stop the program
+  help, h              print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Step,
+    /// Stops once the call depth is back at or below the given depth
+    StepOver(usize),
+    Continue,
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Debugger {
+    /// Reads commands from `input` and writes everything it shows to `output`
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            input,
+            output,
+        }
+    }
+}
+
+impl DebugHook for Debugger {
+    fn before_instruction(&mut self, vm: &VirtualMachine) -> DebugAction {
+        let ip = vm.ip();
+
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::StepOver(depth) => vm.call_depth() <= depth || self.breakpoints.contains(&ip),
+            Mode::Continue => self.breakpoints.contains(&ip),
+        };
+
+        if !stop {
+            return DebugAction::Run;
+        }
+
+        if self.mode == Mode::Continue {
+            self.show(format!("breakpoint at {ip:04}"));
+        }
+
+        self.show_instruction(vm, ip);
+
+        loop {
+            let _ = write!(self.output, "{PROMPT}");
+            let _ = self.output.flush();
+
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(..) => return DebugAction::Quit,
+                Ok(..) => (),
+            }
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+
+            match words.as_slice() {
+                [] => (),
+                ["step" | "s"] => {
+                    self.mode = Mode::Step;
+                    return DebugAction::Run;
+                }
+                ["next" | "n"] => {
+                    let opcode = OpCode::from(vm.byte_code().bytes[ip]);
+
+                    self.mode = match opcode {
+                        OpCode::Call | OpCode::Times | OpCode::Each | OpCode::MapList => {
+                            Mode::StepOver(vm.call_depth())
+                        }
+                        _ => Mode::Step,
+                    };
+
+                    return DebugAction::Run;
+                }
+                ["continue" | "c"] => {
+                    self.mode = Mode::Continue;
+                    return DebugAction::Run;
+                }
+                ["break" | "b", "line", line] => match Debugger::offset_of_line(vm, line) {
+                    Ok(offset) => {
+                        self.breakpoints.insert(offset);
+                        self.show(format!("breakpoint set at {offset:04}"));
+                    }
+                    Err(message) => self.show(message),
+                },
+                ["break" | "b", offset] => match Debugger::parse_offset(vm, offset) {
+                    Ok(offset) => {
+                        self.breakpoints.insert(offset);
+                        self.show(format!("breakpoint set at {offset:04}"));
+                    }
+                    Err(message) => self.show(message),
+                },
+                ["delete" | "d", offset] => match offset.parse::<usize>() {
+                    Ok(offset) if self.breakpoints.remove(&offset) => {
+                        self.show(format!("breakpoint at {offset:04} removed"))
+                    }
+                    _ => self.show(format!("no breakpoint at '{offset}'")),
+                },
+                ["breakpoints" | "info"] => {
+                    if self.breakpoints.is_empty() {
+                        self.show("no breakpoints");
+                    }
+
+                    let breakpoints = self.breakpoints.clone();
+                    for offset in breakpoints.iter() {
+                        self.show(format!("{offset:04}"));
+                    }
+                }
+                ["stack" | "st"] => self.show_stack(vm),
+                ["print" | "p", index] => {
+                    let constant = index
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| vm.byte_code().constants.get(index));
+
+                    match constant {
+                        Some(constant) => self.show(constant),
+                        None => self.show(format!("no constant at '{index}'")),
+                    }
+                }
+                ["list" | "l"] => self.list(vm, ip, 5),
+                ["list" | "l", count] => match count.parse::<usize>() {
+                    Ok(count) => self.list(vm, ip, count),
+                    Err(..) => self.show(format!("'{count}' is not a number")),
+                },
+                ["quit" | "q"] => return DebugAction::Quit,
+                ["help" | "h"] => self.show(HELP),
+                _ => self.show(format!("unknown command '{}', type 'help'", line.trim())),
+            }
+        }
+    }
+}
+
+impl Debugger {
+    /// Writes a line of output, a closed output doesn't stop the program
+    fn show(&mut self, line: impl Display) {
+        let _ = writeln!(self.output, "{line}");
+    }
+
+    fn show_instruction(&mut self, vm: &VirtualMachine, ip: usize) {
+        let entry = vm
+            .debug_info()
+            .and_then(|debug_info| debug_info.entry_at(ip));
+
+        if let Some(entry) = entry {
+            self.show(format!("{} '{}'", entry.location, entry.symbol));
+        }
+
+        let mut offset = ip;
+        let opcode = OpCode::from(vm.byte_code().bytes[ip]);
+        self.show(Disassembler::describe_instruction(
+            vm.byte_code(),
+            opcode,
+            &mut offset,
+        ));
+    }
+
+    fn show_stack(&mut self, vm: &VirtualMachine) {
+        if vm.stack().is_empty() {
+            self.show("<empty>");
+        }
+
+        for (depth, value) in vm.stack().iter().rev().enumerate() {
+            self.show(format!("{depth:4}: {value}"));
+        }
+    }
+
+    fn list(&mut self, vm: &VirtualMachine, ip: usize, count: usize) {
+        let bytes = &vm.byte_code().bytes;
+        let mut offset = ip;
+
+        for _ in 0..count {
+            if offset >= bytes.len() {
+                break;
+            }
+
+            let opcode = OpCode::from(bytes[offset]);
+            self.show(Disassembler::describe_instruction(
+                vm.byte_code(),
+                opcode,
+                &mut offset,
+            ));
+        }
+    }
+
+    fn offset_of_line(vm: &VirtualMachine, line: &str) -> Result<usize, String> {
+        let Some(debug_info) = vm.debug_info() else {
+            return Err("the binary was stripped, set breakpoints by offset".to_string());
+        };
+
+        let Ok(line) = line.parse::<usize>() else {
+            return Err(format!("'{line}' is not a line number"));
+        };
+
+        debug_info
+            .offset_of_line(line)
+            .ok_or(format!("no code on line {line}"))
+    }
+
+    /// Checks that `offset` is the start of an instruction
+    fn parse_offset(vm: &VirtualMachine, offset: &str) -> Result<usize, String> {
+        let Ok(target) = offset.parse::<usize>() else {
+            return Err(format!("'{offset}' is not an offset"));
+        };
+
+        let bytes = &vm.byte_code().bytes;
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            if offset == target {
+                return Ok(target);
+            }
+
+            offset += OpCode::from(bytes[offset]).instruction_size();
+        }
+
+        Err(format!("{target:04} is not the start of an instruction"))
+    }
+}
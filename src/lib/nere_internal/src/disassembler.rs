@@ -1,4 +1,6 @@
-use crate::{ByteCode, OpCode};
+use std::{collections::BTreeSet, fmt::Write};
+
+use crate::{ByteCode, OpCode, Value};
 
 pub struct Disassembler {}
 
@@ -16,31 +18,59 @@ impl Disassembler {
             let byte = byte_code.bytes[offset];
             let opcode = OpCode::from(byte);
             let adjusted = offset - 8;
-            Disassembler::disassemble_instruction_internal(
-                byte_code,
-                opcode,
-                &mut offset,
-                adjusted,
-            );
+            let text = Disassembler::instruction_text(byte_code, opcode, &mut offset, adjusted);
+            println!("{text}");
         }
     }
 
     pub fn disassemble_instruction(byte_code: &ByteCode, opcode: OpCode, offset: &mut usize) {
+        println!(
+            "{}",
+            Disassembler::describe_instruction(byte_code, opcode, offset)
+        );
+    }
+
+    /// The line 'disassemble_instruction' prints for the instruction at `offset`
+    pub fn describe_instruction(
+        byte_code: &ByteCode,
+        opcode: OpCode,
+        offset: &mut usize,
+    ) -> String {
         let adjusted = *offset;
-        Disassembler::disassemble_instruction_internal(byte_code, opcode, offset, adjusted);
+        Disassembler::instruction_text(byte_code, opcode, offset, adjusted)
     }
 
-    fn disassemble_instruction_internal(
+    fn instruction_text(
         byte_code: &ByteCode,
         opcode: OpCode,
         offset: &mut usize,
         adjusted: usize,
-    ) {
+    ) -> String {
+        let mut text = String::new();
+
         if !matches!(
             opcode,
-            OpCode::If(..) | OpCode::Else(..) | OpCode::Do(..) | OpCode::RBrace(..)
+            OpCode::If(..)
+                | OpCode::Else(..)
+                | OpCode::Do(..)
+                | OpCode::RBrace(..)
+                | OpCode::Break(..)
+                | OpCode::Continue(..)
+                | OpCode::For(..)
+                | OpCode::ForStep(..)
+                | OpCode::Next(..)
+                | OpCode::StructNew(..)
+                | OpCode::StructGet(..)
+                | OpCode::StructSet(..)
+                | OpCode::Quote(..)
+                | OpCode::Return
+                | OpCode::Try(..)
+                | OpCode::Catch(..)
+                | OpCode::Assert(..)
+                | OpCode::AssertEq(..)
+                | OpCode::CallNative(..)
         ) {
-            print!("{adjusted:04} [{opcode:?}] ");
+            write!(text, "{adjusted:04} [{opcode:?}] ").unwrap();
         }
 
         match opcode {
@@ -50,29 +80,156 @@ impl Disassembler {
                     .unwrap();
                 let constant_index = usize::from_ne_bytes(bytes);
                 let constant = byte_code.constants[constant_index].clone();
-                println!("{constant_index:04} '{constant}'");
+                write!(text, "{constant_index:04} '{constant}'").unwrap();
                 *offset += 9;
             }
             OpCode::If(..) => {
                 let return_addr = Disassembler::read_isize(byte_code, offset);
-                println!("{adjusted:04} [If] {adjusted:04} -> {return_addr:04}");
+                write!(text, "{adjusted:04} [If] {adjusted:04} -> {return_addr:04}").unwrap();
                 *offset += 9;
             }
             OpCode::Else(..) => {
                 let return_addr = Disassembler::read_isize(byte_code, offset);
-                println!("{adjusted:04} [Else] {adjusted:04} -> {return_addr:04}");
+                write!(
+                    text,
+                    "{adjusted:04} [Else] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
                 *offset += 9;
             }
             OpCode::Do(..) => {
                 let return_addr = Disassembler::read_isize(byte_code, offset);
-                println!("{adjusted:04} [Do] {adjusted:04} -> {return_addr:04}");
+                write!(text, "{adjusted:04} [Do] {adjusted:04} -> {return_addr:04}").unwrap();
                 *offset += 9;
             }
             OpCode::RBrace(..) => {
                 let return_addr = Disassembler::read_isize(byte_code, offset);
-                println!("{adjusted:04} [RBrace] {adjusted:04} -> {return_addr:04}");
+                write!(
+                    text,
+                    "{adjusted:04} [RBrace] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
                 *offset += 9;
             }
+            OpCode::Break(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                let unloop = Disassembler::read_isize_at(byte_code, *offset + 8);
+                write!(
+                    text,
+                    "{adjusted:04} [Break] {adjusted:04} -> {return_addr:04} (unloop {unloop})"
+                )
+                .unwrap();
+                *offset += 17;
+            }
+            OpCode::Continue(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                let unloop = Disassembler::read_isize_at(byte_code, *offset + 8);
+                write!(
+                    text,
+                    "{adjusted:04} [Continue] {adjusted:04} -> {return_addr:04} (unloop {unloop})"
+                )
+                .unwrap();
+                *offset += 17;
+            }
+            OpCode::For(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [For] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::ForStep(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [ForStep] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::Next(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [Next] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::Quote(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [Quote] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::Try(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [Try] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::Catch(..) => {
+                let return_addr = Disassembler::read_isize(byte_code, offset);
+                write!(
+                    text,
+                    "{adjusted:04} [Catch] {adjusted:04} -> {return_addr:04}"
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::Assert(..) | OpCode::AssertEq(..) => {
+                let constant_index = Disassembler::read_isize(byte_code, offset) as usize;
+                let location = &byte_code.constants[constant_index];
+                write!(text, "{adjusted:04} [{opcode:?}] {location}").unwrap();
+                *offset += 9;
+            }
+            OpCode::CallNative(..) => {
+                let index = Disassembler::read_isize(byte_code, offset);
+                let name = &byte_code.natives[index as usize];
+                write!(text, "{adjusted:04} [CallNative] {name}").unwrap();
+                *offset += 9;
+            }
+            OpCode::Return => {
+                write!(text, "{adjusted:04} [Return]").unwrap();
+                *offset += 1;
+            }
+            OpCode::StructNew(..) => {
+                let type_id = Disassembler::read_isize(byte_code, offset);
+                let def = &byte_code.structs[type_id as usize];
+                write!(
+                    text,
+                    "{adjusted:04} [StructNew] {} {:?}",
+                    def.name, def.fields
+                )
+                .unwrap();
+                *offset += 9;
+            }
+            OpCode::StructGet(..) | OpCode::StructSet(..) => {
+                let type_id = Disassembler::read_isize(byte_code, offset);
+                let field = Disassembler::read_isize_at(byte_code, *offset + 8);
+                let def = &byte_code.structs[type_id as usize];
+                write!(
+                    text,
+                    "{adjusted:04} [{}] {}.{}",
+                    if let OpCode::StructGet(..) = opcode {
+                        "StructGet"
+                    } else {
+                        "StructSet"
+                    },
+                    def.name,
+                    def.fields[field as usize]
+                )
+                .unwrap();
+                *offset += 17;
+            }
             OpCode::Dup
             | OpCode::Add
             | OpCode::Sub
@@ -87,15 +244,237 @@ impl Disassembler {
             | OpCode::While
             | OpCode::Dump
             | OpCode::Halt
-            | OpCode::LBrace => {
-                println!();
+            | OpCode::LBrace
+            | OpCode::I
+            | OpCode::J
+            | OpCode::Len
+            | OpCode::Slice
+            | OpCode::IndexOf
+            | OpCode::Split
+            | OpCode::Join
+            | OpCode::Upper
+            | OpCode::Lower
+            | OpCode::Trim
+            | OpCode::CharAt
+            | OpCode::ToInt
+            | OpCode::ToStr
+            | OpCode::Fmt
+            | OpCode::ListStart
+            | OpCode::ListEnd
+            | OpCode::ListPush
+            | OpCode::ListPop
+            | OpCode::ListGet
+            | OpCode::ListSet
+            | OpCode::MapNew
+            | OpCode::MapInsert
+            | OpCode::MapGetOr
+            | OpCode::MapRemove
+            | OpCode::MapContains
+            | OpCode::MapKeys
+            | OpCode::Call
+            | OpCode::Times
+            | OpCode::Each
+            | OpCode::MapList
+            | OpCode::Throw
+            | OpCode::Exit
+            | OpCode::ReadLine
+            | OpCode::ReadAll
+            | OpCode::ReadInt
+            | OpCode::Argc
+            | OpCode::Argv
+            | OpCode::GetEnv
+            | OpCode::Open
+            | OpCode::ReadFile
+            | OpCode::WriteFile
+            | OpCode::AppendFile
+            | OpCode::Exists
+            | OpCode::Delete => {
                 *offset += 1;
             }
         }
+
+        text
+    }
+
+    /// Writes a loaded program in the syntax read by the assembler, see
+    /// `assembler` for a description of it
+    pub fn assembly(byte_code: &ByteCode) -> String {
+        let mut text = String::from("; nere assembly, assemble with 'nereasm'\n");
+
+        if !byte_code.constants.is_empty() {
+            text.push('\n');
+        }
+
+        for (index, constant) in byte_code.constants.iter().enumerate() {
+            writeln!(
+                text,
+                ".const {index} {}",
+                Disassembler::constant_literal(constant)
+            )
+            .unwrap();
+        }
+
+        if !byte_code.structs.is_empty() {
+            text.push('\n');
+        }
+
+        for def in byte_code.structs.iter() {
+            write!(text, ".struct {}", Disassembler::string_literal(&def.name)).unwrap();
+
+            for field in def.fields.iter() {
+                write!(text, " {}", Disassembler::string_literal(field)).unwrap();
+            }
+
+            text.push('\n');
+        }
+
+        if !byte_code.natives.is_empty() {
+            text.push('\n');
+        }
+
+        for name in byte_code.natives.iter() {
+            writeln!(text, ".native {}", Disassembler::string_literal(name)).unwrap();
+        }
+
+        let mut offsets = vec![];
+        let mut offset = 0;
+
+        while offset < byte_code.bytes.len() {
+            offsets.push(offset);
+            offset += OpCode::from(byte_code.bytes[offset]).instruction_size();
+        }
+
+        let targets = offsets
+            .iter()
+            .filter(|&&offset| Disassembler::has_address(OpCode::from(byte_code.bytes[offset])))
+            .map(|&offset| Disassembler::read_isize_at(byte_code, offset))
+            .filter(|target| usize::try_from(*target).is_ok_and(|t| offsets.contains(&t)))
+            .map(|target| target as usize)
+            .collect::<BTreeSet<_>>();
+
+        let lines = byte_code
+            .debug_info
+            .as_ref()
+            .map(|debug_info| debug_info.lines.as_slice())
+            .unwrap_or_default();
+
+        text.push('\n');
+
+        // the line table is sorted by offset, so it is walked alongside the code
+        let mut lines = lines.iter().peekable();
+
+        for &offset in offsets.iter() {
+            let opcode = OpCode::from(byte_code.bytes[offset]);
+
+            if targets.contains(&offset) {
+                writeln!(text, "L{offset:04}:").unwrap();
+            }
+
+            while let Some(entry) = lines.next_if(|entry| entry.offset <= offset) {
+                writeln!(
+                    text,
+                    "    .loc {} {} {} {}",
+                    Disassembler::string_literal(&entry.location.path),
+                    entry.location.line,
+                    entry.location.column,
+                    Disassembler::string_literal(&entry.symbol)
+                )
+                .unwrap();
+            }
+
+            write!(text, "    {}", opcode.mnemonic()).unwrap();
+
+            let operand_count = (opcode.instruction_size() - 1) / 8;
+            for index in 0..operand_count {
+                let operand = Disassembler::read_isize_at(byte_code, offset + index * 8);
+
+                match usize::try_from(operand) {
+                    Ok(target) if index == 0 && targets.contains(&target) => {
+                        write!(text, " L{target:04}").unwrap()
+                    }
+                    _ => write!(text, " {operand}").unwrap(),
+                }
+            }
+
+            if let OpCode::Push = opcode {
+                let index = Disassembler::read_isize_at(byte_code, offset) as usize;
+
+                if let Some(constant) = byte_code.constants.get(index) {
+                    write!(text, " ; {}", Disassembler::constant_literal(constant)).unwrap();
+                }
+            }
+
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Whether the first operand of the instruction is the offset of another instruction
+    fn has_address(opcode: OpCode) -> bool {
+        matches!(
+            opcode,
+            OpCode::If(..)
+                | OpCode::Else(..)
+                | OpCode::Do(..)
+                | OpCode::RBrace(..)
+                | OpCode::Break(..)
+                | OpCode::Continue(..)
+                | OpCode::For(..)
+                | OpCode::ForStep(..)
+                | OpCode::Next(..)
+                | OpCode::Quote(..)
+                | OpCode::Try(..)
+                | OpCode::Catch(..)
+        )
+    }
+
+    fn constant_literal(constant: &Value) -> String {
+        match constant {
+            Value::Int32(int32) => format!("i32 {int32}"),
+            Value::UInt32(uint32) => format!("u32 {uint32}"),
+            Value::String(string) => format!("str {}", Disassembler::string_literal(string)),
+            Value::List(items) => {
+                let mut literal = String::from("list [");
+
+                for item in items.iter() {
+                    write!(literal, " {}", Disassembler::constant_literal(item)).unwrap();
+                }
+
+                literal.push_str(" ]");
+                literal
+            }
+            // these have no literal syntax so they never end up in the constant pool
+            Value::Map(..) | Value::Struct(..) | Value::Quotation(..) | Value::File(..) => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn string_literal(string: &str) -> String {
+        let mut literal = String::from('"');
+
+        for c in string.chars() {
+            match c {
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                '\n' => literal.push_str("\\n"),
+                '\r' => literal.push_str("\\r"),
+                '\t' => literal.push_str("\\t"),
+                c => literal.push(c),
+            }
+        }
+
+        literal.push('"');
+        literal
     }
 
     fn read_isize(byte_code: &ByteCode, offset: &mut usize) -> isize {
-        let bytes: [u8; 8] = byte_code.bytes[(*offset + 1)..=(*offset + 8)]
+        Disassembler::read_isize_at(byte_code, *offset)
+    }
+
+    fn read_isize_at(byte_code: &ByteCode, offset: usize) -> isize {
+        let bytes: [u8; 8] = byte_code.bytes[(offset + 1)..=(offset + 8)]
             .try_into()
             .unwrap();
         isize::from_ne_bytes(bytes)
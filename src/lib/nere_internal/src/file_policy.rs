@@ -0,0 +1,86 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// The directories a program may read from and write to, nothing
+/// outside of them is touched
+#[derive(Debug, Default, Clone)]
+pub struct FilePolicy {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+}
+
+impl FilePolicy {
+    /// Resolves `path` and checks it against the policy, the returned
+    /// path is the one that should be used to access the file
+    pub fn check(&self, path: &str, access: Access) -> Result<PathBuf, Error> {
+        let resolved = FilePolicy::resolve(Path::new(path));
+
+        let allowed = match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write,
+        };
+
+        if allowed
+            .iter()
+            .any(|dir| resolved.starts_with(FilePolicy::resolve(dir)))
+        {
+            return Ok(resolved);
+        }
+
+        let (verb, flag) = match access {
+            Access::Read => ("reading", "--allow-read"),
+            Access::Write => ("writing", "--allow-write"),
+        };
+
+        Err(Error::RuntimeError(format!(
+            "permission denied: {verb} '{path}' is not allowed, use '{flag}' to grant access"
+        )))
+    }
+
+    /// Makes a path absolute without '.' and '..' components, following symlinks
+    /// as far as the path exists so that they can't be used to escape a directory
+    fn resolve(path: &Path) -> PathBuf {
+        let absolute = match std::env::current_dir() {
+            Ok(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        };
+
+        let mut normalized = PathBuf::new();
+        for component in absolute.components() {
+            match component {
+                Component::CurDir => (),
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+
+        let mut missing = vec![];
+        let mut existing = normalized.as_path();
+
+        loop {
+            if let Ok(canonical) = existing.canonicalize() {
+                return missing
+                    .iter()
+                    .rev()
+                    .fold(canonical, |path, name| path.join(name));
+            }
+
+            match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name.to_os_string());
+                    existing = parent;
+                }
+                _ => return normalized,
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{Error, Value};
+
+/// The largest width or precision a placeholder can ask for
+pub const MAX_WIDTH: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Decimal,
+    LowerHex,
+    UpperHex,
+    Binary,
+    Octal,
+}
+
+/// The options of a single `{:spec}` placeholder,
+/// spec is `[[fill]align][0][width][.precision][x|X|b|o]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub zero_pad: bool,
+    pub width: usize,
+    pub precision: Option<usize>,
+    pub radix: Radix,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            zero_pad: false,
+            width: 0,
+            precision: None,
+            radix: Radix::Decimal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Placeholder(FormatSpec),
+}
+
+pub struct Format {
+    pub segments: Vec<Segment>,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err("unterminated '{' in format string".to_string()),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    let spec = match spec.strip_prefix(':') {
+                        Some(spec) => Format::parse_spec(spec)?,
+                        None if spec.is_empty() => FormatSpec::default(),
+                        None => return Err(format!("invalid placeholder '{{{spec}}}'")),
+                    };
+
+                    segments.push(Segment::Placeholder(spec));
+                }
+                '}' => return Err("unmatched '}' in format string, use '}}' instead".to_string()),
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn placeholder_count(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|s| matches!(s, Segment::Placeholder(..)))
+            .count()
+    }
+
+    /// The most bytes that formatting `values` can take up, known before
+    /// any of them are formatted so that a heap limit can be checked first
+    pub fn max_len(&self, values: &[Value]) -> usize {
+        let mut values = values.iter();
+
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.len(),
+                Segment::Placeholder(spec) => {
+                    let Some(value) = values.next() else {
+                        return 0;
+                    };
+
+                    // binary digits are the longest way to write an integer,
+                    // a precision adds a point and that many zeros
+                    let text = if value.is_integer() {
+                        value.display_len().max(u32::BITS as usize)
+                            + spec.precision.map_or(0, |precision| precision + 1)
+                    } else {
+                        value.display_len()
+                    };
+
+                    text + spec.width * spec.fill.len_utf8()
+                }
+            })
+            .sum()
+    }
+
+    /// Formats `values` in order, one for each placeholder
+    pub fn format(&self, values: &[Value]) -> Result<String, Error> {
+        debug_assert!(values.len() == self.placeholder_count());
+
+        let mut result = String::new();
+        let mut values = values.iter();
+
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => result.push_str(literal),
+                Segment::Placeholder(spec) => {
+                    let value = values.next().unwrap();
+                    result.push_str(&Format::format_value(value, spec)?);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn parse_spec(spec: &str) -> Result<FormatSpec, String> {
+        let chars = spec.chars().collect::<Vec<_>>();
+        let mut result = FormatSpec::default();
+        let mut i = 0;
+
+        let align_from = |c: char| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+
+        if chars.len() >= 2 && align_from(chars[1]).is_some() {
+            result.fill = chars[0];
+            result.align = align_from(chars[1]);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(|c| align_from(*c)) {
+            result.align = Some(align);
+            i = 1;
+        }
+
+        if chars.get(i) == Some(&'0') {
+            result.zero_pad = true;
+            i += 1;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i > start {
+            result.width = Format::parse_count(&chars[start..i], "width", spec)?;
+        }
+
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i == start {
+                return Err(format!("missing precision in '{{:{spec}}}'"));
+            }
+            result.precision = Some(Format::parse_count(&chars[start..i], "precision", spec)?);
+        }
+
+        if let Some(c) = chars.get(i) {
+            result.radix = match c {
+                'x' => Radix::LowerHex,
+                'X' => Radix::UpperHex,
+                'b' => Radix::Binary,
+                'o' => Radix::Octal,
+                _ => return Err(format!("unknown format specifier '{c}' in '{{:{spec}}}'")),
+            };
+            i += 1;
+        }
+
+        if i != chars.len() {
+            return Err(format!("invalid format spec '{{:{spec}}}'"));
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the digits of a width or precision, which can be at most `MAX_WIDTH`
+    fn parse_count(digits: &[char], name: &str, spec: &str) -> Result<usize, String> {
+        digits
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+            .filter(|count| *count <= MAX_WIDTH)
+            .ok_or_else(|| {
+                format!("{name} too large in '{{:{spec}}}', at most {MAX_WIDTH} is allowed")
+            })
+    }
+
+    fn format_value(value: &Value, spec: &FormatSpec) -> Result<String, Error> {
+        let text = match value {
+            Value::String(string) => {
+                if spec.radix != Radix::Decimal {
+                    return Err(Error::RuntimeError(
+                        "radix specifiers can only be used to format integers".to_string(),
+                    ));
+                }
+
+                match spec.precision {
+                    Some(precision) => string.chars().take(precision).collect(),
+                    None => string.clone(),
+                }
+            }
+            _ if !value.is_integer() => {
+                if spec.radix != Radix::Decimal {
+                    return Err(Error::RuntimeError(
+                        "radix specifiers can only be used to format integers".to_string(),
+                    ));
+                }
+
+                value.to_string()
+            }
+            _ => {
+                let digits = match (spec.radix, value) {
+                    (Radix::Decimal, _) => value.to_string(),
+                    (Radix::LowerHex, Value::Int32(int32)) => format!("{int32:x}"),
+                    (Radix::LowerHex, _) => format!("{:x}", value.as_u32()),
+                    (Radix::UpperHex, Value::Int32(int32)) => format!("{int32:X}"),
+                    (Radix::UpperHex, _) => format!("{:X}", value.as_u32()),
+                    (Radix::Binary, Value::Int32(int32)) => format!("{int32:b}"),
+                    (Radix::Binary, _) => format!("{:b}", value.as_u32()),
+                    (Radix::Octal, Value::Int32(int32)) => format!("{int32:o}"),
+                    (Radix::Octal, _) => format!("{:o}", value.as_u32()),
+                };
+
+                // integers are printed like floats when given a precision
+                match spec.precision {
+                    Some(precision) if precision > 0 && spec.radix == Radix::Decimal => {
+                        format!("{digits}.{}", "0".repeat(precision))
+                    }
+                    _ => digits,
+                }
+            }
+        };
+
+        let len = text.chars().count();
+        if len >= spec.width {
+            return Ok(text);
+        }
+
+        let padding = spec.width - len;
+
+        if spec.zero_pad && spec.align.is_none() && value.is_integer() {
+            return Ok(match text.strip_prefix('-') {
+                Some(digits) => format!("-{}{digits}", "0".repeat(padding)),
+                None => format!("{}{text}", "0".repeat(padding)),
+            });
+        }
+
+        // numbers line up to the right by default, strings to the left
+        let align = spec.align.unwrap_or(if value.is_integer() {
+            Align::Right
+        } else {
+            Align::Left
+        });
+
+        let fill = |n: usize| spec.fill.to_string().repeat(n);
+
+        Ok(match align {
+            Align::Left => format!("{text}{}", fill(padding)),
+            Align::Right => format!("{}{text}", fill(padding)),
+            Align::Center => format!("{}{text}{}", fill(padding / 2), fill(padding - padding / 2)),
+        })
+    }
+}
@@ -77,18 +77,41 @@ impl Lexer {
                 tokens.push(number);
                 continue;
             } else if c.is_alphabetic() {
-                while self.peek().is_alphabetic() && !self.is_at_end() {
+                // words may contain dashes, e.g. 'for-step'
+                while (self.peek().is_alphabetic()
+                    || self.peek() == '-' && self.peek_next().is_alphabetic())
+                    && !self.is_at_end()
+                {
                     self.advance();
                 }
 
+                // predicates end in a question mark, e.g. 'exists?'
+                if self.peek() == '?' {
+                    self.advance();
+                }
+
+                // struct field words, e.g. 'Point.x@' and 'Point.x!'
+                if self.peek() == '.' && self.peek_next().is_alphabetic() {
+                    self.advance();
+
+                    while self.peek().is_alphabetic() && !self.is_at_end() {
+                        self.advance();
+                    }
+
+                    if self.peek() == '@' || self.peek() == '!' {
+                        self.advance();
+                    }
+                }
+
                 let lexeme = self.current_lexeme();
                 if self.instruction_set.contains_key(&lexeme) {
                     let opcode = self.instruction_set.get(&lexeme).unwrap();
                     let instruction = self.make_token(TokenType::Instruction(*opcode), lexeme);
                     tokens.push(instruction);
                 } else {
-                    let err = self.error_token(format!("unknown identifier '{lexeme}'"));
-                    tokens.push(err);
+                    // user defined words are resolved by the compiler
+                    let word = self.make_token(TokenType::Word(lexeme.clone()), lexeme);
+                    tokens.push(word);
                 }
 
                 continue;
@@ -106,6 +129,26 @@ impl Lexer {
                         self.make_token(TokenType::Instruction(OpCode::RBrace(-1)), lexeme);
                     tokens.push(rbrace);
                 }
+                '[' if self.matches(':') => {
+                    let lexeme = self.current_lexeme();
+                    let start = self.make_token(TokenType::Instruction(OpCode::Quote(-1)), lexeme);
+                    tokens.push(start);
+                }
+                ':' if self.matches(']') => {
+                    let lexeme = self.current_lexeme();
+                    let end = self.make_token(TokenType::Instruction(OpCode::Return), lexeme);
+                    tokens.push(end);
+                }
+                '[' => {
+                    let lexeme = self.current_lexeme();
+                    let start = self.make_token(TokenType::Instruction(OpCode::ListStart), lexeme);
+                    tokens.push(start);
+                }
+                ']' => {
+                    let lexeme = self.current_lexeme();
+                    let end = self.make_token(TokenType::Instruction(OpCode::ListEnd), lexeme);
+                    tokens.push(end);
+                }
                 '+' => {
                     let lexeme = self.current_lexeme();
                     let add = self.make_token(TokenType::Instruction(OpCode::Add), lexeme);
@@ -164,12 +207,10 @@ impl Lexer {
                     tokens.push(dump);
                 }
                 ';' => {
-                    // skip over comments
+                    // skip over comments, the newline is left for line counting
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
-
-                    self.advance();
                 }
                 '"' => {
                     while !self.is_at_end() && self.peek() != '"' {
@@ -196,6 +237,22 @@ impl Lexer {
                     let string = self.make_token(TokenType::Value(value), lexeme);
                     tokens.push(string);
                 }
+                '\'' => {
+                    while self.peek().is_alphabetic() && !self.is_at_end() {
+                        self.advance();
+                    }
+
+                    let lexeme = self.current_lexeme();
+                    if lexeme.len() == 1 {
+                        let err = self.error_token("expected loop label after '''".to_string());
+                        tokens.push(err);
+                        continue;
+                    }
+
+                    let name = lexeme[1..].to_string();
+                    let label = self.make_token(TokenType::Label(name), lexeme);
+                    tokens.push(label);
+                }
                 '\r' | '\t' | ' ' => (),
                 '\n' => {
                     self.line += 1;
@@ -231,6 +288,14 @@ impl Lexer {
         self.chars[self.cursor]
     }
 
+    fn peek_next(&self) -> char {
+        if self.cursor + 1 >= self.chars.len() {
+            return '\0';
+        }
+
+        self.chars[self.cursor + 1]
+    }
+
     fn matches(&mut self, c: char) -> bool {
         if self.peek() == c {
             self.advance();
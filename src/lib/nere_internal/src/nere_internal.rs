@@ -1,10 +1,24 @@
+pub mod assembler;
+pub mod compiler;
+pub mod coverage;
+pub mod debugger;
 pub mod disassembler;
+pub mod file_policy;
+pub mod format;
 pub mod lexer;
+pub mod profile;
 pub mod timer;
+pub mod virtual_machine;
 
-use std::ops::{Add, Div, Mul, Sub};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::{Add, Div, Mul, Sub},
+    rc::Rc,
+};
 
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
 
 pub enum Error {
     RuntimeError(String),
@@ -14,17 +28,108 @@ pub enum Error {
     InvalidFilepath(String),
     InvalidExtension(String),
     FailedToCreateFile(String),
+    /// A value thrown with 'throw' that no 'catch' handled, with the ip of the throw
+    Thrown(Value, usize),
     InvalidUTF8String,
     CorruptedBinary,
+    /// A native function declared with 'extern' that the host never registered
+    UnresolvedNative(String),
+    /// Execution was stopped by a limit, with the ip and the number of instructions run
+    LimitExceeded(Limit, usize, u64),
+    /// A runtime error with the instruction it happened at and
+    /// the line of source code, when it could be read
+    Located(Box<Error>, LineEntry, Option<String>),
+    /// A runtime error with the quotation calls that led up to it
+    Traced(Box<Error>, Backtrace),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Fuel,
+    Deadline,
+}
+
+/// The state of the vm when a program failed, innermost frame first
+#[derive(Debug, Clone)]
+pub struct Backtrace {
+    pub frames: Vec<BacktraceFrame>,
+    /// The top of the operand stack, top first
+    pub stack: Vec<Value>,
+    /// How many values the operand stack held in total
+    pub stack_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktraceFrame {
+    /// 'main' or the quotation that was running
+    pub name: String,
+    /// Where the quotation was written, None for 'main' and stripped binaries
+    pub defined_at: Option<Location>,
+    /// The failing instruction in the innermost frame, the call in the others
+    pub ip: usize,
+    pub entry: Option<LineEntry>,
+}
+
+impl std::fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stack backtrace:")?;
+
+        for (depth, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n{depth:4}: {}", frame.name)?;
+
+            if let Some(loc) = &frame.defined_at {
+                write!(f, " {loc}")?;
+            }
+
+            write!(f, "\n            at {:04}", frame.ip)?;
+
+            if let Some(entry) = &frame.entry {
+                write!(f, " {} '{}'", entry.location, entry.symbol)?;
+            }
+        }
+
+        match self.stack_len {
+            1 => write!(f, "\noperand stack (1 value):")?,
+            len => write!(f, "\noperand stack ({len} values):")?,
+        }
+
+        for (depth, value) in self.stack.iter().enumerate() {
+            write!(f, "\n{depth:4}: {value}")?;
+        }
+
+        if self.stack_len > self.stack.len() {
+            write!(f, "\n      ... {} more", self.stack_len - self.stack.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error {
+    /// The status a process should exit with after failing with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::RuntimeError(..)
+            | Error::SegFault(..)
+            | Error::Thrown(..)
+            | Error::LimitExceeded(..) => exit_code::RUNTIME_ERROR,
+            Error::ParseError(..) | Error::CompileError(..) => exit_code::COMPILE_ERROR,
+            Error::InvalidFilepath(..)
+            | Error::InvalidExtension(..)
+            | Error::FailedToCreateFile(..)
+            | Error::InvalidUTF8String
+            | Error::CorruptedBinary
+            | Error::UnresolvedNative(..) => exit_code::LOAD_ERROR,
+            Error::Located(err, ..) | Error::Traced(err, ..) => err.exit_code(),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::RuntimeError(err) => write!(f, "{}: {err}", "uncaught runtime error".red()),
-            Error::SegFault(ip, err) => {
-                write!(f, "{}: at ip: {ip}, {err}", "segmentation fault".red())
-            }
+            Error::SegFault(_, err) => write!(f, "{}: {err}", "segmentation fault".red()),
             Error::ParseError(err) => write!(f, "{err}"),
             Error::CompileError(err, loc) => write!(f, "{loc} {}: {err}", "compile error".red()),
             Error::InvalidFilepath(err) => write!(f, "{}: '{err}'", "invalid filepath".red()),
@@ -32,6 +137,7 @@ impl std::fmt::Display for Error {
             Error::FailedToCreateFile(err) => {
                 write!(f, "{}: '{err}'", "failed to create file".red())
             }
+            Error::Thrown(value, _) => write!(f, "{}: {value}", "uncaught exception".red()),
             Error::InvalidUTF8String => {
                 write!(
                     f,
@@ -42,6 +148,46 @@ impl std::fmt::Display for Error {
             Error::CorruptedBinary => {
                 write!(f, "{}: failed to read bytecode", "corrupted binary".red())
             }
+            Error::LimitExceeded(limit, ip, count) => {
+                let reason = match limit {
+                    Limit::Fuel => "ran out of fuel",
+                    Limit::Deadline => "reached the deadline",
+                };
+
+                write!(
+                    f,
+                    "{}: {reason} at ip {ip:04} after {count} instructions",
+                    "execution limit exceeded".red()
+                )
+            }
+            Error::UnresolvedNative(name) => write!(
+                f,
+                "{}: '{name}' is declared with 'extern' but was never registered",
+                "unresolved native function".red()
+            ),
+            Error::Located(err, entry, snippet) => {
+                let loc = &entry.location;
+                write!(f, "{loc} {err}")?;
+
+                if let Some(snippet) = snippet {
+                    // locations point at the last character of a token, so the
+                    // symbol is underlined back from there when it fits on the line
+                    let width = match entry.symbol.chars().count() {
+                        width if !entry.symbol.contains('\n') && width <= loc.column => width,
+                        _ => 1,
+                    };
+
+                    let gutter = loc.line.to_string().len();
+                    let indent = " ".repeat(loc.column.saturating_sub(width));
+
+                    write!(f, "\n{:gutter$} |", "")?;
+                    write!(f, "\n{} | {snippet}", loc.line)?;
+                    write!(f, "\n{:gutter$} | {indent}{}", "", "^".repeat(width).red())?;
+                }
+
+                Ok(())
+            }
+            Error::Traced(err, backtrace) => write!(f, "{err}\n{backtrace}"),
         }
     }
 }
@@ -68,9 +214,112 @@ pub enum OpCode {
     Halt,
     LBrace,
     RBrace(isize),
+    Break(isize, isize),
+    Continue(isize, isize),
+    For(isize),
+    ForStep(isize),
+    Next(isize),
+    I,
+    J,
+    Len,
+    Slice,
+    IndexOf,
+    Split,
+    Join,
+    Upper,
+    Lower,
+    Trim,
+    CharAt,
+    ToInt,
+    ToStr,
+    Fmt,
+    ListStart,
+    ListEnd,
+    ListPush,
+    ListPop,
+    ListGet,
+    ListSet,
+    MapNew,
+    MapInsert,
+    MapGetOr,
+    MapRemove,
+    MapContains,
+    MapKeys,
+    StructNew(isize),
+    StructGet(isize, isize),
+    StructSet(isize, isize),
+    Quote(isize),
+    Return,
+    Call,
+    Times,
+    Each,
+    MapList,
+    Try(isize),
+    Catch(isize),
+    Throw,
+    Assert(isize),
+    AssertEq(isize),
+    Exit,
+    ReadLine,
+    ReadAll,
+    ReadInt,
+    Argc,
+    Argv,
+    GetEnv,
+    Open,
+    ReadFile,
+    WriteFile,
+    AppendFile,
+    Exists,
+    Delete,
+    CallNative(isize),
 }
 
 impl OpCode {
+    /// The number of bytes the instruction takes up in a binary, operands included
+    pub fn instruction_size(&self) -> usize {
+        use OpCode::*;
+        match self {
+            Break(..) | Continue(..) | StructGet(..) | StructSet(..) => 17,
+            If(..) | Else(..) | Do(..) | RBrace(..) | For(..) | ForStep(..) | Next(..)
+            | StructNew(..) | Quote(..) | Try(..) | Catch(..) | Assert(..) | AssertEq(..)
+            | CallNative(..) | Push => 9,
+            Dup | Add | Sub | Mul | Div | Lt | Lte | Gt | Gte | Eq | Ne | While | Dump | Halt
+            | LBrace | I | J | Len | Slice | IndexOf | Split | Join | Upper | Lower | Trim
+            | CharAt | ToInt | ToStr | Fmt | ListStart | ListEnd | ListPush | ListPop | ListGet
+            | ListSet | MapNew | MapInsert | MapGetOr | MapRemove | MapContains | MapKeys
+            | Return | Call | Times | Each | MapList | Throw | Exit | ReadLine | ReadAll
+            | ReadInt | Argc | Argv | GetEnv | Open | ReadFile | WriteFile | AppendFile
+            | Exists | Delete => 1,
+        }
+    }
+
+    /// The byte of the last opcode, every byte up to it decodes to an opcode
+    pub const MAX_BYTE: u8 = 78;
+
+    /// The name of the instruction in assembly, its variant name in kebab case
+    pub fn mnemonic(&self) -> String {
+        let name = format!("{self:?}");
+        let name = name.split('(').next().unwrap_or_default();
+
+        let mut mnemonic = String::new();
+        for (i, c) in name.chars().enumerate() {
+            if c.is_ascii_uppercase() && i != 0 {
+                mnemonic.push('-');
+            }
+
+            mnemonic.push(c.to_ascii_lowercase());
+        }
+
+        mnemonic
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<OpCode> {
+        (0..=OpCode::MAX_BYTE)
+            .map(OpCode::from)
+            .find(|opcode| opcode.mnemonic() == mnemonic)
+    }
+
     pub fn as_byte(&self) -> u8 {
         use OpCode::*;
         match self {
@@ -94,6 +343,65 @@ impl OpCode {
             Halt => 17,
             LBrace => 18,
             RBrace(..) => 19,
+            Break(..) => 20,
+            Continue(..) => 21,
+            For(..) => 22,
+            ForStep(..) => 23,
+            Next(..) => 24,
+            I => 25,
+            J => 26,
+            Len => 27,
+            Slice => 28,
+            IndexOf => 29,
+            Split => 30,
+            Join => 31,
+            Upper => 32,
+            Lower => 33,
+            Trim => 34,
+            CharAt => 35,
+            ToInt => 36,
+            ToStr => 37,
+            Fmt => 38,
+            ListStart => 39,
+            ListEnd => 40,
+            ListPush => 41,
+            ListPop => 42,
+            ListGet => 43,
+            ListSet => 44,
+            MapNew => 45,
+            MapInsert => 46,
+            MapGetOr => 47,
+            MapRemove => 48,
+            MapContains => 49,
+            MapKeys => 50,
+            StructNew(..) => 51,
+            StructGet(..) => 52,
+            StructSet(..) => 53,
+            Quote(..) => 54,
+            Return => 55,
+            Call => 56,
+            Times => 57,
+            Each => 58,
+            MapList => 59,
+            Try(..) => 60,
+            Catch(..) => 61,
+            Throw => 62,
+            Assert(..) => 63,
+            AssertEq(..) => 64,
+            Exit => 65,
+            ReadLine => 66,
+            ReadAll => 67,
+            ReadInt => 68,
+            Argc => 69,
+            Argv => 70,
+            GetEnv => 71,
+            Open => 72,
+            ReadFile => 73,
+            WriteFile => 74,
+            AppendFile => 75,
+            Exists => 76,
+            Delete => 77,
+            CallNative(..) => 78,
         }
     }
 }
@@ -122,6 +430,65 @@ impl From<u8> for OpCode {
             17 => Halt,
             18 => LBrace,
             19 => RBrace(-1),
+            20 => Break(-1, 0),
+            21 => Continue(-1, 0),
+            22 => For(-1),
+            23 => ForStep(-1),
+            24 => Next(-1),
+            25 => I,
+            26 => J,
+            27 => Len,
+            28 => Slice,
+            29 => IndexOf,
+            30 => Split,
+            31 => Join,
+            32 => Upper,
+            33 => Lower,
+            34 => Trim,
+            35 => CharAt,
+            36 => ToInt,
+            37 => ToStr,
+            38 => Fmt,
+            39 => ListStart,
+            40 => ListEnd,
+            41 => ListPush,
+            42 => ListPop,
+            43 => ListGet,
+            44 => ListSet,
+            45 => MapNew,
+            46 => MapInsert,
+            47 => MapGetOr,
+            48 => MapRemove,
+            49 => MapContains,
+            50 => MapKeys,
+            51 => StructNew(-1),
+            52 => StructGet(-1, -1),
+            53 => StructSet(-1, -1),
+            54 => Quote(-1),
+            55 => Return,
+            56 => Call,
+            57 => Times,
+            58 => Each,
+            59 => MapList,
+            60 => Try(-1),
+            61 => Catch(-1),
+            62 => Throw,
+            63 => Assert(-1),
+            64 => AssertEq(-1),
+            65 => Exit,
+            66 => ReadLine,
+            67 => ReadAll,
+            68 => ReadInt,
+            69 => Argc,
+            70 => Argv,
+            71 => GetEnv,
+            72 => Open,
+            73 => ReadFile,
+            74 => WriteFile,
+            75 => AppendFile,
+            76 => Exists,
+            77 => Delete,
+            78 => CallNative(-1),
             _ => unreachable!(),
         }
     }
@@ -132,6 +499,30 @@ pub enum Value {
     Int32(i32),
     UInt32(u32),
     String(String),
+    /// Lists have value semantics, 'dup' shares the items until either
+    /// copy is modified, at which point the modified copy is cloned
+    List(Rc<Vec<Value>>),
+    /// Maps share the value semantics of lists, entries are kept sorted
+    /// by key so that iteration order is the same on every run
+    Map(Rc<BTreeMap<Value, Value>>),
+    Struct(StructValue),
+    /// A reference to the code of a quotation by its instruction pointer
+    Quotation(usize),
+    /// A file opened with 'open', holding the path that passed the file policy
+    File(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StructValue {
+    pub type_id: usize,
+    pub def: Rc<StructDef>,
+    pub fields: Rc<Vec<Value>>,
 }
 
 impl Value {
@@ -140,6 +531,49 @@ impl Value {
             Value::Int32(..) => 0,
             Value::UInt32(..) => 1,
             Value::String(..) => 2,
+            Value::List(..) => 3,
+            Value::Map(..) => 4,
+            Value::Struct(..) => 5,
+            Value::Quotation(..) => 6,
+            Value::File(..) => 7,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int32(..) => "i32",
+            Value::UInt32(..) => "u32",
+            Value::String(..) => "string",
+            Value::List(..) => "list",
+            Value::Map(..) => "map",
+            Value::Struct(..) => "struct",
+            Value::Quotation(..) => "quotation",
+            Value::File(..) => "file",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Value::Int32(..) | Value::UInt32(..))
+    }
+
+    /// The number of bytes `to_string` would return, counted without building it
+    pub fn display_len(&self) -> usize {
+        struct Counter(usize);
+
+        impl std::fmt::Write for Counter {
+            fn write_str(&mut self, s: &str) -> std::fmt::Result {
+                self.0 += s.len();
+                Ok(())
+            }
+        }
+
+        match self {
+            Value::String(string) => string.len(),
+            _ => {
+                let mut counter = Counter(0);
+                let _ = std::fmt::write(&mut counter, format_args!("{self}"));
+                counter.0
+            }
         }
     }
 
@@ -155,91 +589,242 @@ impl Value {
         match self {
             Value::Int32(..) => self.as_i32(),
             Value::UInt32(..) => self.as_u32() as i32,
-            Value::String(..) => unreachable!(),
+            _ => unreachable!(),
         }
     }
 
     pub fn as_u32(&self) -> u32 {
-        debug_assert!(self.constant_type() == 0);
+        debug_assert!(self.constant_type() == 1);
         if let Value::UInt32(uint32) = self {
             return *uint32;
         }
         0
     }
 
+    pub fn as_u32_implicit(&self) -> u32 {
+        match self {
+            Value::Int32(..) => self.as_i32() as u32,
+            Value::UInt32(..) => self.as_u32(),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn as_string(&self) -> String {
-        debug_assert!(self.constant_type() == 1);
+        debug_assert!(self.constant_type() == 2);
         if let Value::String(string) = self {
             return string.clone();
         }
         "".to_string()
     }
+
+    /// Orders two values for the comparison instructions.
+    ///
+    /// Integers compare numerically regardless of their type, so `1 1u32 =` is true.
+    /// Strings compare lexicographically by unicode code point, which means
+    /// uppercase letters sort before lowercase ones and a prefix sorts before
+    /// any longer string that starts with it (`"ab" "abc" <` is true).
+    /// Lists compare lexicographically item by item in the same way,
+    /// maps entry by entry in key order and structs of the same type field by field.
+    /// Ordering values of different kinds is a type error.
+    pub fn compare(&self, rhs: &Value) -> Result<Ordering, Error> {
+        match (self, rhs) {
+            (Value::String(lhs), Value::String(rhs)) => Ok(lhs.cmp(rhs)),
+            (Value::List(lhs), Value::List(rhs)) => {
+                for (lhs, rhs) in lhs.iter().zip(rhs.iter()) {
+                    match lhs.compare(rhs)? {
+                        Ordering::Equal => continue,
+                        ordering => return Ok(ordering),
+                    }
+                }
+
+                Ok(lhs.len().cmp(&rhs.len()))
+            }
+            (Value::Map(lhs), Value::Map(rhs)) => {
+                for ((lhs_key, lhs_value), (rhs_key, rhs_value)) in lhs.iter().zip(rhs.iter()) {
+                    match lhs_key.compare(rhs_key)? {
+                        Ordering::Equal => (),
+                        ordering => return Ok(ordering),
+                    }
+
+                    match lhs_value.compare(rhs_value)? {
+                        Ordering::Equal => (),
+                        ordering => return Ok(ordering),
+                    }
+                }
+
+                Ok(lhs.len().cmp(&rhs.len()))
+            }
+            (Value::Quotation(lhs), Value::Quotation(rhs)) if lhs == rhs => Ok(Ordering::Equal),
+            (Value::File(lhs), Value::File(rhs)) => Ok(lhs.cmp(rhs)),
+            (Value::Struct(lhs), Value::Struct(rhs)) if lhs.type_id == rhs.type_id => {
+                let lhs = Value::List(lhs.fields.clone());
+                let rhs = Value::List(rhs.fields.clone());
+                lhs.compare(&rhs)
+            }
+            (lhs, rhs) if lhs.is_integer() && rhs.is_integer() => {
+                let lhs = match lhs {
+                    Value::UInt32(uint32) => *uint32 as i64,
+                    _ => lhs.as_i32() as i64,
+                };
+                let rhs = match rhs {
+                    Value::UInt32(uint32) => *uint32 as i64,
+                    _ => rhs.as_i32() as i64,
+                };
+                Ok(lhs.cmp(&rhs))
+            }
+            _ => Err(Value::type_error("compare", self, rhs)),
+        }
+    }
+
+    /// Converts a value into the form it is stored as in a map,
+    /// only strings and integers can be used as keys. Integers are stored
+    /// as 'i32' whenever they fit so that `1` and `1u32` name the same entry.
+    pub fn into_map_key(self) -> Result<Value, Error> {
+        match self {
+            Value::Int32(..) | Value::String(..) => Ok(self),
+            Value::UInt32(uint32) => Ok(match i32::try_from(uint32) {
+                Ok(int32) => Value::Int32(int32),
+                Err(..) => self,
+            }),
+            _ => Err(Error::RuntimeError(format!(
+                "map keys must be strings or integers but found '{}'",
+                self.type_name()
+            ))),
+        }
+    }
+
+    /// Equality used by `=` and `!`, values of different kinds are never equal.
+    pub fn equals(&self, rhs: &Value) -> bool {
+        matches!(self.compare(rhs), Ok(Ordering::Equal))
+    }
+
+    fn type_error(operation: &str, lhs: &Value, rhs: &Value) -> Error {
+        Error::RuntimeError(format!(
+            "cannot {operation} '{}' and '{}'",
+            lhs.type_name(),
+            rhs.type_name()
+        ))
+    }
+
+    fn arithmetic(
+        &self,
+        operation: &str,
+        rhs: &Value,
+        int32: fn(i32, i32) -> Option<i32>,
+        uint32: fn(u32, u32) -> Option<u32>,
+    ) -> Result<Value, Error> {
+        if !self.is_integer() || !rhs.is_integer() {
+            return Err(Value::type_error(operation, self, rhs));
+        }
+
+        let result = match *self {
+            Value::Int32(lhs) => int32(lhs, rhs.as_i32_implicit()).map(Value::Int32),
+            Value::UInt32(lhs) => uint32(lhs, rhs.as_u32_implicit()).map(Value::UInt32),
+            _ => unreachable!(),
+        };
+
+        result.ok_or_else(|| {
+            Error::RuntimeError(format!(
+                "integer overflow when trying to {operation} {self} and {rhs}"
+            ))
+        })
+    }
 }
 
 impl Add for Value {
-    type Output = Self;
+    type Output = Result<Value, Error>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        debug_assert!(self.constant_type() == rhs.constant_type());
+        match self {
+            // appends to the string in place rather than copying it
+            Value::String(lhs) => Ok(Value::String(lhs + &rhs.to_string())),
+            _ => &self + &rhs,
+        }
+    }
+}
 
+impl Add for &Value {
+    type Output = Result<Value, Error>;
+
+    fn add(self, rhs: Self) -> Self::Output {
         match self {
-            Value::Int32(lhs) => Value::Int32(lhs + rhs.as_i32_implicit()),
-            Value::UInt32(lhs) => Value::UInt32(lhs + rhs.as_u32()),
-            Value::String(lhs) => match rhs {
-                Value::Int32(int32) => Value::String(lhs + &int32.to_string()),
-                Value::UInt32(uint32) => Value::String(lhs + &uint32.to_string()),
-                Value::String(rhs) => Value::String(lhs + &rhs),
-            },
+            Value::String(lhs) => Ok(Value::String(format!("{lhs}{rhs}"))),
+            _ => self.arithmetic("add", rhs, i32::checked_add, u32::checked_add),
         }
     }
 }
 
 impl Sub for Value {
-    type Output = Self;
+    type Output = Result<Value, Error>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        debug_assert!(self.constant_type() == rhs.constant_type());
+        &self - &rhs
+    }
+}
 
-        match self {
-            Value::Int32(lhs) => Value::Int32(lhs - rhs.as_i32_implicit()),
-            Value::UInt32(lhs) => Value::UInt32(lhs - rhs.as_u32()),
-            Value::String(..) => todo!(),
-        }
+impl Sub for &Value {
+    type Output = Result<Value, Error>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.arithmetic("subtract", rhs, i32::checked_sub, u32::checked_sub)
     }
 }
 
 impl Mul for Value {
-    type Output = Self;
+    type Output = Result<Value, Error>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        debug_assert!(self.constant_type() == rhs.constant_type());
+        &self * &rhs
+    }
+}
 
-        match self {
-            Value::Int32(lhs) => Value::Int32(lhs * rhs.as_i32_implicit()),
-            Value::UInt32(lhs) => Value::UInt32(lhs * rhs.as_u32()),
-            Value::String(..) => todo!(),
+impl Mul for &Value {
+    type Output = Result<Value, Error>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::String(string), count) if count.is_integer() => {
+                let count = count.as_i32_implicit();
+                if count < 0 {
+                    return Err(Error::RuntimeError(format!(
+                        "cannot repeat a string a negative number of times ({count})"
+                    )));
+                }
+
+                Ok(Value::String(string.repeat(count as usize)))
+            }
+            _ => self.arithmetic("multiply", rhs, i32::checked_mul, u32::checked_mul),
         }
     }
 }
 
 impl Div for Value {
-    type Output = Self;
+    type Output = Result<Value, Error>;
 
     fn div(self, rhs: Self) -> Self::Output {
-        debug_assert!(self.constant_type() == rhs.constant_type());
+        &self / &rhs
+    }
+}
+
+impl Div for &Value {
+    type Output = Result<Value, Error>;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        if rhs.is_integer() && rhs.as_i32_implicit() == 0 {
+            return Err(Error::RuntimeError("division by zero".to_string()));
+        }
 
+        self.arithmetic("divide", rhs, i32::checked_div, u32::checked_div)
+    }
+}
+
+impl Value {
+    /// Writes a value that is contained in a list or map, strings are
+    /// quoted so that their bounds are visible
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Int32(lhs) => {
-                let rhs = rhs.as_i32_implicit();
-                debug_assert!(rhs != 0);
-                Value::Int32(lhs / rhs)
-            }
-            Value::UInt32(lhs) => {
-                let rhs = rhs.as_u32();
-                debug_assert!(rhs != 0);
-                Value::UInt32(lhs / rhs)
-            }
-            Value::String(..) => todo!(),
+            Value::String(string) => write!(f, "{string:?}"),
+            _ => write!(f, "{self}"),
         }
     }
 }
@@ -250,6 +835,51 @@ impl std::fmt::Display for Value {
             Value::Int32(int32) => write!(f, "{int32}"),
             Value::UInt32(uint32) => write!(f, "{uint32}"),
             Value::String(string) => write!(f, "{string}"),
+            Value::List(items) => {
+                write!(f, "[")?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    item.fmt_nested(f)?;
+                }
+
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+
+                write!(f, "}}")
+            }
+            Value::Struct(value) => {
+                write!(f, "{}{{", value.def.name)?;
+
+                let fields = value.def.fields.iter().zip(value.fields.iter());
+                for (i, (name, field)) in fields.enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{name}: ")?;
+                    field.fmt_nested(f)?;
+                }
+
+                write!(f, "}}")
+            }
+            Value::Quotation(ip) => write!(f, "[: {ip:04} :]"),
+            Value::File(path) => write!(f, "<file '{path}'>"),
         }
     }
 }
@@ -258,17 +888,169 @@ impl std::fmt::Display for Value {
 pub struct ByteCode {
     pub bytes: Vec<u8>,
     pub constants: Vec<Value>,
+    pub structs: Vec<Rc<StructDef>>,
+    /// The names of the native functions the program calls
+    pub natives: Vec<String>,
+    /// Left out of binaries compiled with '--strip'
+    pub debug_info: Option<DebugInfo>,
+}
+
+/// Why the code of a program can't be run, see `ByteCode::validate`
+#[derive(Debug, Clone)]
+pub struct InvalidCode {
+    /// The offset of the offending instruction
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl ByteCode {
+    /// Checks that every instruction is complete, that the constants, structs
+    /// and natives they refer to exist and that jumps land on an instruction
+    pub fn validate(&self) -> Result<(), InvalidCode> {
+        let mut starts = HashSet::new();
+        let mut jumps = vec![];
+        let mut loops = HashMap::new();
+        let mut nexts = vec![];
+        let mut offset = 0;
+
+        while offset < self.bytes.len() {
+            let invalid = |reason: String| Err(InvalidCode { offset, reason });
+
+            if self.bytes[offset] > OpCode::MAX_BYTE {
+                return invalid(format!("unknown opcode {}", self.bytes[offset]));
+            }
+
+            let opcode = OpCode::from(self.bytes[offset]);
+            let size = opcode.instruction_size();
+
+            if offset + size > self.bytes.len() {
+                return invalid(format!("'{}' is missing its operands", opcode.mnemonic()));
+            }
+
+            let operand = |index: usize| {
+                let start = offset + 1 + index * 8;
+                isize::from_ne_bytes(self.bytes[start..start + 8].try_into().unwrap())
+            };
+
+            let in_range = |value: isize, len: usize| value >= 0 && (value as usize) < len;
+
+            use OpCode::*;
+            match opcode {
+                Push | Assert(..) | AssertEq(..) if !in_range(operand(0), self.constants.len()) => {
+                    return invalid(format!("there is no constant {}", operand(0)));
+                }
+                StructNew(..) | StructGet(..) | StructSet(..)
+                    if !in_range(operand(0), self.structs.len()) =>
+                {
+                    return invalid(format!("there is no struct {}", operand(0)));
+                }
+                StructGet(..) | StructSet(..) => {
+                    let def = &self.structs[operand(0) as usize];
+
+                    if !in_range(operand(1), def.fields.len()) {
+                        return invalid(format!("'{}' has no field {}", def.name, operand(1)));
+                    }
+                }
+                CallNative(..) if !in_range(operand(0), self.natives.len()) => {
+                    return invalid(format!("there is no native {}", operand(0)));
+                }
+                Break(..) | Continue(..) if operand(1) < 0 => {
+                    return invalid(format!("can not leave {} loops", operand(1)));
+                }
+                // blocks that aren't loops don't jump back
+                RBrace(..) if operand(0) < 0 => (),
+                For(..) | ForStep(..) => {
+                    loops.insert(offset, operand(0));
+                    jumps.push((offset, operand(0)));
+                }
+                Next(..) => {
+                    nexts.push((offset, operand(0)));
+                    jumps.push((offset, operand(0)));
+                }
+                If(..) | Else(..) | Do(..) | RBrace(..) | Break(..) | Continue(..) | Quote(..)
+                | Try(..) | Catch(..) => {
+                    jumps.push((offset, operand(0)));
+                }
+                _ => (),
+            }
+
+            starts.insert(offset);
+            offset += size;
+        }
+
+        for (offset, target) in jumps {
+            if target < 0 || !starts.contains(&(target as usize)) {
+                return Err(InvalidCode {
+                    offset,
+                    reason: format!("jump to {target} does not land on an instruction"),
+                });
+            }
+        }
+
+        // a 'next' jumps back to the body of the 'for' that jumps past it
+        for (offset, target) in nexts {
+            let paired = (target as usize)
+                .checked_sub(9)
+                .and_then(|entry| loops.get(&entry))
+                .is_some_and(|exit| *exit == (offset + 9) as isize);
+
+            if !paired {
+                return Err(InvalidCode {
+                    offset,
+                    reason: "'next' does not close a 'for' loop".to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps the offset of every instruction back to the source it was compiled from
+#[derive(Debug, Default, Clone)]
+pub struct DebugInfo {
+    /// Sorted by offset
+    pub lines: Vec<LineEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineEntry {
+    pub offset: usize,
+    pub location: Location,
+    /// The word or literal as it was written in the source
+    pub symbol: String,
+}
+
+impl DebugInfo {
+    /// The entry of the instruction that covers `offset`
+    pub fn entry_at(&self, offset: usize) -> Option<&LineEntry> {
+        match self.lines.partition_point(|entry| entry.offset <= offset) {
+            0 => None,
+            index => Some(&self.lines[index - 1]),
+        }
+    }
+
+    /// The offset of the first instruction compiled from `line`
+    pub fn offset_of_line(&self, line: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .filter(|entry| entry.location.line == line)
+            .map(|entry| entry.offset)
+            .min()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TokenType {
     Instruction(OpCode),
     Value(Value),
+    Label(String),
+    Word(String),
     Error,
     Eof,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Location {
     pub path: String,
     pub line: usize,
@@ -294,18 +1076,79 @@ impl std::fmt::Display for Token {
     }
 }
 
+/// The exit statuses of 'nere' and 'nerec', programs choose their own with 'exit'
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    /// An uncaught runtime error or exception
+    pub const RUNTIME_ERROR: i32 = 1;
+    /// The source failed to parse or compile
+    pub const COMPILE_ERROR: i32 = 2;
+    /// A file could not be read, written or loaded as a binary
+    pub const LOAD_ERROR: i32 = 3;
+}
+
 pub mod utils {
     use std::{collections::HashMap, path::Path};
 
     use crate::OpCode;
 
     pub fn get_instruction_set() -> HashMap<String, OpCode> {
-        vec![
+        [
             ("dup", OpCode::Dup),
             ("if", OpCode::If(-1)),
             ("else", OpCode::Else(-1)),
             ("while", OpCode::While),
             ("do", OpCode::Do(-1)),
+            ("break", OpCode::Break(-1, 0)),
+            ("continue", OpCode::Continue(-1, 0)),
+            ("for", OpCode::For(-1)),
+            ("for-step", OpCode::ForStep(-1)),
+            ("i", OpCode::I),
+            ("j", OpCode::J),
+            ("len", OpCode::Len),
+            ("slice", OpCode::Slice),
+            ("index-of", OpCode::IndexOf),
+            ("split", OpCode::Split),
+            ("join", OpCode::Join),
+            ("upper", OpCode::Upper),
+            ("lower", OpCode::Lower),
+            ("trim", OpCode::Trim),
+            ("char-at", OpCode::CharAt),
+            ("to-int", OpCode::ToInt),
+            ("to-string", OpCode::ToStr),
+            ("fmt", OpCode::Fmt),
+            ("push", OpCode::ListPush),
+            ("pop", OpCode::ListPop),
+            ("get", OpCode::ListGet),
+            ("set", OpCode::ListSet),
+            ("new-map", OpCode::MapNew),
+            ("insert", OpCode::MapInsert),
+            ("get-or", OpCode::MapGetOr),
+            ("remove", OpCode::MapRemove),
+            ("contains", OpCode::MapContains),
+            ("keys", OpCode::MapKeys),
+            ("call", OpCode::Call),
+            ("times", OpCode::Times),
+            ("each", OpCode::Each),
+            ("map", OpCode::MapList),
+            ("try", OpCode::Try(-1)),
+            ("catch", OpCode::Catch(-1)),
+            ("throw", OpCode::Throw),
+            ("assert", OpCode::Assert(-1)),
+            ("assert-eq", OpCode::AssertEq(-1)),
+            ("exit", OpCode::Exit),
+            ("read-line", OpCode::ReadLine),
+            ("read-all", OpCode::ReadAll),
+            ("read-int", OpCode::ReadInt),
+            ("argc", OpCode::Argc),
+            ("argv", OpCode::Argv),
+            ("getenv", OpCode::GetEnv),
+            ("open", OpCode::Open),
+            ("read-file", OpCode::ReadFile),
+            ("write-file", OpCode::WriteFile),
+            ("append-file", OpCode::AppendFile),
+            ("exists?", OpCode::Exists),
+            ("delete", OpCode::Delete),
         ]
         .iter()
         .map(|(k, v)| (k.to_string(), *v))
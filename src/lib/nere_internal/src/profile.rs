@@ -0,0 +1,95 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use crate::{ByteCode, OpCode};
+
+/// How often something ran and how long it took
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sample {
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl Sample {
+    fn add(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+/// What a program spent its time on, recorded by the vm
+/// when `RuntimeOptions::profile` is set
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    /// Keyed by the offset of the instruction
+    pub instructions: BTreeMap<usize, Sample>,
+    /// Keyed by the byte of the opcode
+    pub opcodes: BTreeMap<u8, Sample>,
+    /// The number of instructions run under each call stack, keyed by the
+    /// entry of every quotation on the return stack followed by the offset
+    /// of the instruction, outermost first
+    pub stacks: HashMap<Vec<usize>, u64>,
+    pub total: Duration,
+}
+
+impl Profile {
+    pub fn record(&mut self, ip: usize, opcode: u8, frames: &[usize], time: Duration) {
+        self.instructions.entry(ip).or_default().add(time);
+        self.opcodes.entry(opcode).or_default().add(time);
+        self.total += time;
+
+        let mut stack = frames.to_vec();
+        stack.push(ip);
+        *self.stacks.entry(stack).or_default() += 1;
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.opcodes.values().map(|sample| sample.count).sum()
+    }
+
+    /// One line per call stack in the collapsed format flame graph tools
+    /// read, `main;quotation <loc>;add <loc> 42`, weighted by the number
+    /// of instructions run under it and sorted
+    pub fn collapsed_stacks(&self, byte_code: &ByteCode) -> Vec<String> {
+        let mut lines = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let (ip, frames) = stack.split_last().unwrap();
+                let mut names = vec!["main".to_string()];
+
+                // the body of a quotation starts right after its 'Quote' instruction
+                let quote_size = OpCode::Quote(0).instruction_size();
+                for &entry in frames.iter() {
+                    names.push(format!(
+                        "quotation {}",
+                        locate(byte_code, entry.saturating_sub(quote_size))
+                    ));
+                }
+
+                names.push(format!(
+                    "{} {}",
+                    OpCode::from(byte_code.bytes[*ip]).mnemonic(),
+                    locate(byte_code, *ip)
+                ));
+
+                format!("{} {count}", names.join(";"))
+            })
+            .collect::<Vec<_>>();
+
+        lines.sort();
+        lines
+    }
+}
+
+/// The source location of `offset` or the offset itself for stripped binaries
+fn locate(byte_code: &ByteCode, offset: usize) -> String {
+    byte_code
+        .debug_info
+        .as_ref()
+        .and_then(|debug_info| debug_info.entry_at(offset))
+        .map(|entry| entry.location.to_string())
+        .unwrap_or_else(|| format!("{offset:04}"))
+}
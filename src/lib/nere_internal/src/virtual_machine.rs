@@ -0,0 +1,1987 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+
+use crate::{
+    coverage::Coverage,
+    disassembler::Disassembler,
+    exit_code,
+    file_policy::{Access, FilePolicy},
+    format::Format,
+    profile::Profile,
+    utils, Backtrace, BacktraceFrame, ByteCode, DebugInfo, Error, Limit, LineEntry, Location,
+    OpCode, StructDef, StructValue, Value,
+};
+
+pub type RuntimeResult<T> = std::result::Result<T, Error>;
+
+/// What the vm should do after a debug hook has seen the next instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    Run,
+    Quit,
+}
+
+/// Drives a debugger, called before every instruction with the vm
+/// stopped at the instruction that is about to run
+pub trait DebugHook {
+    fn before_instruction(&mut self, vm: &VirtualMachine) -> DebugAction;
+}
+
+/// The operand stack as seen by native functions
+pub type Stack = Vec<Value>;
+
+/// A host function that nere code calls through an 'extern' declaration
+pub type NativeFn = dyn Fn(&mut Stack) -> Result<(), Error>;
+
+struct Native {
+    name: String,
+    pops: usize,
+    pushes: usize,
+    function: Box<NativeFn>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The stack after each instruction, `[ 1 ] [ 2 ]`
+    #[default]
+    Text,
+    /// One json object per instruction with its ip, opcode,
+    /// operands and the stack after it ran
+    Json,
+}
+
+/// Settings for a single run of a program
+#[derive(Debug, Default, Clone)]
+pub struct RuntimeOptions {
+    /// Show a breakdown of the bytecode during execution
+    pub disassemble: bool,
+    /// Show a breakdown of the stack during execution
+    pub stack_trace: bool,
+    /// How each line of the stack trace is written
+    pub trace_format: TraceFormat,
+    /// Where the file instructions may read and write
+    pub file_policy: FilePolicy,
+    /// The number of instructions the program may run
+    pub fuel: Option<u64>,
+    /// How long the program may run for
+    pub timeout: Option<Duration>,
+    /// The number of values the operand stack may hold
+    pub max_stack: Option<usize>,
+    /// How deep quotation calls may nest
+    pub max_return_stack: Option<usize>,
+    /// The number of bytes live strings and aggregate values may take up,
+    /// instructions check the size of their result before allocating it
+    pub max_heap_bytes: Option<usize>,
+    /// Count and time every instruction, read back with `VirtualMachine::profile`
+    pub profile: bool,
+    /// Record the instructions and branches that ran, read back with `VirtualMachine::coverage`
+    pub coverage: bool,
+}
+
+const STACK_CAPACITY_START: usize = 256;
+
+/// Reading the clock on every instruction is too slow, the
+/// deadline is only checked once per this many instructions
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// How many values from the top of the operand stack a backtrace shows
+const BACKTRACE_STACK_VALUES: usize = 8;
+
+/// The control state of a running counted loop
+struct LoopFrame {
+    index: i32,
+    limit: i32,
+    step: i32,
+    /// Set when iterating over the items of a list
+    items: Option<Rc<Vec<Value>>>,
+    /// The number of list literals that were open when the loop started
+    list_depth: usize,
+}
+
+impl LoopFrame {
+    fn is_finished(&self) -> bool {
+        if self.step > 0 {
+            self.index >= self.limit
+        } else {
+            self.index <= self.limit
+        }
+    }
+}
+
+/// What a quotation returns to once its code has finished
+enum FrameKind {
+    Call,
+    Times {
+        quotation: usize,
+        remaining: i32,
+    },
+    Each {
+        quotation: usize,
+        items: Rc<Vec<Value>>,
+        index: usize,
+    },
+    Map {
+        quotation: usize,
+        items: Rc<Vec<Value>>,
+        index: usize,
+        results: Vec<Value>,
+    },
+}
+
+struct Frame {
+    return_addr: usize,
+    /// The first instruction of the quotation that was called
+    entry: usize,
+    kind: FrameKind,
+}
+
+/// The state to restore when a value is thrown inside of a try block
+struct Handler {
+    catch_addr: usize,
+    stack_depth: usize,
+    loop_depth: usize,
+    list_depth: usize,
+    return_depth: usize,
+}
+
+pub struct VirtualMachine {
+    stack: Vec<Value>,
+    loop_stack: Vec<LoopFrame>,
+    list_marks: Vec<usize>,
+    return_stack: Vec<Frame>,
+    handlers: Vec<Handler>,
+    args: Vec<String>,
+    natives: Vec<Native>,
+    /// The registry index of each native the program declares, filled in before running
+    linked_natives: Vec<usize>,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// Where the stack trace goes, the program output when None
+    trace: Option<Box<dyn Write>>,
+    exit_code: i32,
+    instruction_count: u64,
+    max_heap_bytes: Option<usize>,
+    /// Bytes allocated by the program, an upper bound since dropped values
+    /// aren't subtracted until the count is close to the limit
+    heap_bytes: usize,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
+    byte_code: ByteCode,
+    ip: usize,
+}
+
+impl VirtualMachine {
+    pub fn new() -> Self {
+        Self {
+            stack: Vec::with_capacity(STACK_CAPACITY_START),
+            loop_stack: vec![],
+            list_marks: vec![],
+            return_stack: vec![],
+            handlers: vec![],
+            args: vec![],
+            natives: vec![],
+            linked_natives: vec![],
+            input: Box::new(BufReader::new(std::io::stdin())),
+            output: Box::new(std::io::stdout()),
+            trace: None,
+            exit_code: exit_code::SUCCESS,
+            instruction_count: 0,
+            max_heap_bytes: None,
+            heap_bytes: 0,
+            profile: None,
+            coverage: None,
+            byte_code: ByteCode::default(),
+            ip: 0,
+        }
+    }
+
+    /// Runs the loaded program, returns the status it exited with
+    pub fn execute(&mut self, options: &RuntimeOptions) -> RuntimeResult<i32> {
+        self.run(options, None)
+    }
+
+    /// Runs the loaded program under the control of a debugger
+    pub fn execute_with_hook(
+        &mut self,
+        options: &RuntimeOptions,
+        hook: &mut dyn DebugHook,
+    ) -> RuntimeResult<i32> {
+        self.run(options, Some(hook))
+    }
+
+    /// Runs the loaded program from the instruction at `ip` on `stack`,
+    /// returns the status it exited with. This lets 'nere test' start each
+    /// test from what the top level code left behind without running it again
+    pub fn execute_from(
+        &mut self,
+        ip: usize,
+        stack: Vec<Value>,
+        options: &RuntimeOptions,
+    ) -> RuntimeResult<i32> {
+        self.stack = stack;
+        self.jmp(ip)?;
+
+        self.run(options, None)
+    }
+
+    /// The offset of the next instruction
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    pub fn byte_code(&self) -> &ByteCode {
+        &self.byte_code
+    }
+
+    /// The number of quotation calls that haven't returned yet
+    pub fn call_depth(&self) -> usize {
+        self.return_stack.len()
+    }
+
+    /// What the last run spent its time on, when it ran with `RuntimeOptions::profile`
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// What the last run executed, when it ran with `RuntimeOptions::coverage`
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// The line table of the loaded program, None for stripped binaries
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.byte_code.debug_info.as_ref()
+    }
+
+    fn run(
+        &mut self,
+        options: &RuntimeOptions,
+        mut hook: Option<&mut dyn DebugHook>,
+    ) -> RuntimeResult<i32> {
+        self.link_natives()?;
+
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        self.profile = options.profile.then(Profile::default);
+        self.coverage = options.coverage.then(Coverage::default);
+        self.max_heap_bytes = options.max_heap_bytes;
+        self.heap_bytes = self.live_bytes();
+
+        loop {
+            if self.is_at_end() {
+                break;
+            }
+
+            if let Some(hook) = hook.as_deref_mut() {
+                if hook.before_instruction(self) == DebugAction::Quit {
+                    break;
+                }
+            }
+
+            self.check_limits(options.fuel, deadline)?;
+
+            let ip = self.ip;
+            let sample = options.profile.then(|| self.start_sample());
+            let result = self
+                .step(options)
+                .and_then(|running| self.check_memory(options).map(|_| running));
+
+            if let (Some((frames, started)), Some(profile)) = (sample, self.profile.as_mut()) {
+                let opcode = self.byte_code.bytes[ip];
+                profile.record(ip, opcode, &frames, started.elapsed());
+            }
+
+            if let Some(coverage) = self.coverage.as_mut() {
+                coverage.record(ip);
+
+                // an 'if' that didn't jump falls through into its block
+                let opcode = OpCode::from(self.byte_code.bytes[ip]);
+                if let (OpCode::If(..), Ok(..)) = (opcode, &result) {
+                    coverage.record_branch(ip, self.ip == ip + opcode.instruction_size());
+                }
+            }
+
+            match result {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => self
+                    .unwind(err)
+                    .map_err(|err| self.trace(self.locate(err, ip), ip))?,
+            }
+
+            if options.stack_trace {
+                self.write_trace(options.trace_format, ip)?;
+            }
+        }
+
+        self.output
+            .flush()
+            .map_err(|err| Error::RuntimeError(format!("failed to write output: {err}")))?;
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace
+                .flush()
+                .map_err(|err| Error::RuntimeError(format!("failed to write trace: {err}")))?;
+        }
+
+        Ok(self.exit_code)
+    }
+
+    /// Writes the stack after the instruction at `ip` has run
+    fn write_trace(&mut self, format: TraceFormat, ip: usize) -> RuntimeResult<()> {
+        let line = match format {
+            TraceFormat::Text => {
+                let mut trace = String::new();
+
+                for value in self.stack.iter() {
+                    trace.push_str(&format!("[ {value} ] "));
+                }
+
+                if self.stack.is_empty() {
+                    trace.push_str("[ ]");
+                }
+
+                trace
+            }
+            TraceFormat::Json => {
+                let opcode = OpCode::from(self.byte_code.bytes[ip]);
+
+                // operands are the 8 byte words that follow the opcode
+                let operands = (ip + 1..ip + opcode.instruction_size())
+                    .step_by(8)
+                    .map(|offset| {
+                        let bytes: [u8; 8] =
+                            self.byte_code.bytes[offset..offset + 8].try_into().unwrap();
+                        isize::from_ne_bytes(bytes)
+                    })
+                    .collect::<Vec<_>>();
+
+                serde_json::json!({
+                    "step": self.instruction_count,
+                    "ip": ip,
+                    "opcode": opcode.mnemonic(),
+                    "operands": operands,
+                    "stack": self.stack.iter().map(Self::trace_value).collect::<Vec<_>>(),
+                })
+                .to_string()
+            }
+        };
+
+        match self.trace.as_mut() {
+            Some(trace) => writeln!(trace, "{line}")
+                .map_err(|err| Error::RuntimeError(format!("failed to write trace: {err}"))),
+            None => self.write_line(&line),
+        }
+    }
+
+    /// Numbers, strings and lists keep their json types, everything
+    /// else is written the way the program would print it
+    fn trace_value(value: &Value) -> serde_json::Value {
+        match value {
+            Value::Int32(int32) => (*int32).into(),
+            Value::UInt32(uint32) => (*uint32).into(),
+            Value::String(string) => string.as_str().into(),
+            Value::List(items) => items.iter().map(Self::trace_value).collect(),
+            Value::Map(..) | Value::Struct(..) | Value::Quotation(..) | Value::File(..) => {
+                value.to_string().into()
+            }
+        }
+    }
+
+    /// The quotations being run, read before the clock is started
+    /// so that collecting them isn't counted
+    fn start_sample(&self) -> (Vec<usize>, Instant) {
+        let frames = self.return_stack.iter().map(|frame| frame.entry).collect();
+        (frames, Instant::now())
+    }
+
+    /// Adds the source location of the instruction at `ip` to an error
+    /// raised by the program, when the binary has debug info
+    fn locate(&self, err: Error, ip: usize) -> Error {
+        if !matches!(
+            err,
+            Error::RuntimeError(..) | Error::SegFault(..) | Error::Thrown(..)
+        ) {
+            return err;
+        }
+
+        let Some(entry) = self
+            .byte_code
+            .debug_info
+            .as_ref()
+            .and_then(|debug_info| debug_info.entry_at(ip))
+        else {
+            return err;
+        };
+
+        let snippet = std::fs::read_to_string(&entry.location.path)
+            .ok()
+            .and_then(|source| {
+                source
+                    .lines()
+                    .nth(entry.location.line.saturating_sub(1))
+                    .map(str::to_string)
+            });
+
+        Error::Located(Box::new(err), entry.clone(), snippet)
+    }
+
+    /// The location stored with a failed assertion, only needed in the
+    /// message when `locate` has no debug info to point at it instead
+    fn assert_location(&self, ip: usize, location: Value) -> String {
+        let located = self
+            .byte_code
+            .debug_info
+            .as_ref()
+            .is_some_and(|debug_info| debug_info.entry_at(ip).is_some());
+
+        if located {
+            String::new()
+        } else {
+            format!(" at {location}")
+        }
+    }
+
+    /// Adds the quotation calls on the return stack and the top of the
+    /// operand stack to an error that `locate` annotated
+    fn trace(&self, err: Error, ip: usize) -> Error {
+        if !matches!(
+            err,
+            Error::Located(..) | Error::RuntimeError(..) | Error::SegFault(..) | Error::Thrown(..)
+        ) {
+            return err;
+        }
+
+        let debug_info = self.byte_code.debug_info.as_ref();
+        let entry_at = |ip| debug_info.and_then(|debug_info| debug_info.entry_at(ip).cloned());
+
+        let mut frames = vec![];
+        let mut ip = ip;
+
+        for frame in self.return_stack.iter().rev() {
+            let word = match frame.kind {
+                FrameKind::Call => "call",
+                FrameKind::Times { .. } => "times",
+                FrameKind::Each { .. } => "each",
+                FrameKind::Map { .. } => "map",
+            };
+
+            // the body of a quotation starts right after its 'Quote' instruction
+            let quote_size = OpCode::Quote(0).instruction_size();
+            let defined_at =
+                entry_at(frame.entry.saturating_sub(quote_size)).map(|entry| entry.location);
+
+            frames.push(BacktraceFrame {
+                name: format!("quotation run by '{word}'"),
+                defined_at,
+                ip,
+                entry: entry_at(ip),
+            });
+
+            // every word that calls a quotation is a single byte
+            ip = frame.return_addr - 1;
+        }
+
+        frames.push(BacktraceFrame {
+            name: "main".to_string(),
+            defined_at: None,
+            ip,
+            entry: entry_at(ip),
+        });
+
+        let backtrace = Backtrace {
+            frames,
+            stack: self
+                .stack
+                .iter()
+                .rev()
+                .take(BACKTRACE_STACK_VALUES)
+                .cloned()
+                .collect(),
+            stack_len: self.stack.len(),
+        };
+
+        Error::Traced(Box::new(err), backtrace)
+    }
+
+    fn check_limits(&mut self, fuel: Option<u64>, deadline: Option<Instant>) -> RuntimeResult<()> {
+        let exceeded = |limit| Error::LimitExceeded(limit, self.ip, self.instruction_count);
+
+        if fuel.is_some_and(|fuel| self.instruction_count >= fuel) {
+            return Err(exceeded(Limit::Fuel));
+        }
+
+        if let Some(deadline) = deadline {
+            if self
+                .instruction_count
+                .is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && Instant::now() >= deadline
+            {
+                return Err(exceeded(Limit::Deadline));
+            }
+        }
+
+        self.instruction_count += 1;
+        Ok(())
+    }
+
+    fn check_memory(&self, options: &RuntimeOptions) -> RuntimeResult<()> {
+        if let Some(max) = options.max_stack {
+            if self.stack.len() > max {
+                return Err(Error::RuntimeError(format!(
+                    "stack overflow: the operand stack holds more than {max} values"
+                )));
+            }
+        }
+
+        if let Some(max) = options.max_return_stack {
+            if self.return_stack.len() > max {
+                return Err(Error::RuntimeError(format!(
+                    "stack overflow: quotation calls are nested more than {max} deep"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts `bytes` against the heap limit before they are allocated, the
+    /// live values are only measured once the running count passes the limit
+    fn allocate(&mut self, bytes: usize) -> RuntimeResult<()> {
+        let Some(max) = self.max_heap_bytes else {
+            return Ok(());
+        };
+
+        self.heap_bytes = self.heap_bytes.saturating_add(bytes);
+        if self.heap_bytes <= max {
+            return Ok(());
+        }
+
+        let live = self.live_bytes();
+        let used = live.saturating_add(bytes);
+
+        if used > max {
+            self.heap_bytes = live;
+            return Err(Error::RuntimeError(format!(
+                "out of memory: live values would take up {used} bytes but the limit is {max}"
+            )));
+        }
+
+        self.heap_bytes = used;
+        Ok(())
+    }
+
+    /// Counts the copy of a list's items that modifying a shared list makes
+    fn allocate_copy<'a>(
+        &mut self,
+        shared: bool,
+        items: impl Iterator<Item = &'a Value>,
+    ) -> RuntimeResult<()> {
+        if !shared {
+            return Ok(());
+        }
+
+        let bytes = items
+            .map(|item| std::mem::size_of::<Value>() + Self::copy_bytes(item))
+            .sum();
+        self.allocate(bytes)
+    }
+
+    /// What cloning a value allocates, the contents of lists, maps and
+    /// structs are shared rather than copied
+    fn copy_bytes(value: &Value) -> usize {
+        match value {
+            Value::String(string) | Value::File(string) => string.len(),
+            _ => 0,
+        }
+    }
+
+    /// The bytes taken up by every value the program can still reach
+    fn live_bytes(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut total = 0;
+
+        for value in self.stack.iter() {
+            total += Self::value_bytes(value, &mut seen);
+        }
+
+        // lists being iterated are kept alive by their loops and quotation calls
+        for frame in self.return_stack.iter() {
+            match &frame.kind {
+                FrameKind::Each { items, .. } => {
+                    total += Self::value_bytes(&Value::List(items.clone()), &mut seen);
+                }
+                FrameKind::Map { items, results, .. } => {
+                    total += Self::value_bytes(&Value::List(items.clone()), &mut seen);
+
+                    for result in results.iter() {
+                        total += Self::value_bytes(result, &mut seen);
+                    }
+                }
+                FrameKind::Call | FrameKind::Times { .. } => (),
+            }
+        }
+
+        for items in self
+            .loop_stack
+            .iter()
+            .filter_map(|frame| frame.items.as_ref())
+        {
+            total += Self::value_bytes(&Value::List(items.clone()), &mut seen);
+        }
+
+        total
+    }
+
+    /// Values shared through an `Rc` are only counted the first time they are seen
+    fn value_bytes(value: &Value, seen: &mut HashSet<usize>) -> usize {
+        let items_bytes = |count: usize| count * std::mem::size_of::<Value>();
+
+        match value {
+            Value::Int32(..) | Value::UInt32(..) | Value::Quotation(..) => 0,
+            Value::String(string) | Value::File(string) => string.len(),
+            Value::List(items) => {
+                if !seen.insert(Rc::as_ptr(items) as usize) {
+                    return 0;
+                }
+
+                let nested: usize = items.iter().map(|item| Self::value_bytes(item, seen)).sum();
+                items_bytes(items.len()) + nested
+            }
+            Value::Map(entries) => {
+                if !seen.insert(Rc::as_ptr(entries) as usize) {
+                    return 0;
+                }
+
+                let nested: usize = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        Self::value_bytes(key, seen) + Self::value_bytes(value, seen)
+                    })
+                    .sum();
+                items_bytes(entries.len() * 2) + nested
+            }
+            Value::Struct(value) => {
+                if !seen.insert(Rc::as_ptr(&value.fields) as usize) {
+                    return 0;
+                }
+
+                let nested: usize = value
+                    .fields
+                    .iter()
+                    .map(|field| Self::value_bytes(field, seen))
+                    .sum();
+                items_bytes(value.fields.len()) + nested
+            }
+        }
+    }
+
+    /// Runs a single instruction, returns false once the program halts
+    fn step(&mut self, options: &RuntimeOptions) -> RuntimeResult<bool> {
+        let ip = self.advance();
+
+        let byte = self.byte_code.bytes[ip];
+        let opcode = OpCode::from(byte);
+
+        if options.disassemble {
+            let mut offset = ip;
+            Disassembler::disassemble_instruction(&self.byte_code, opcode, &mut offset)
+        }
+
+        match opcode {
+            OpCode::Push => {
+                let constant = self.read_constant()?;
+                self.allocate(Self::copy_bytes(&constant))?;
+                self.stack.push(constant);
+            }
+            OpCode::Dup => {
+                let Some(value) = self.stack.last() else {
+                    return Err(Error::RuntimeError("stack underflow".to_string()));
+                };
+
+                let value = value.clone();
+                self.allocate(Self::copy_bytes(&value))?;
+                self.stack.push(value);
+            }
+            OpCode::Add => {
+                // appending to a string grows it by the text of the right side
+                if let [.., Value::String(..), rhs] = self.stack.as_slice() {
+                    let grows = match rhs {
+                        Value::String(string) => string.len(),
+                        value => value.to_string().len(),
+                    };
+                    self.allocate(grows)?;
+
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    self.stack.push((lhs + rhs)?);
+                } else {
+                    self.binary(|lhs, rhs| lhs + rhs)?;
+                }
+            }
+            OpCode::Sub => self.binary(|lhs, rhs| lhs - rhs)?,
+            OpCode::Mul => {
+                if let [.., Value::String(string), count] = self.stack.as_slice() {
+                    if count.is_integer() {
+                        let count = count.as_i32_implicit().max(0) as usize;
+                        self.allocate(string.len().saturating_mul(count))?;
+                    }
+                }
+
+                self.binary(|lhs, rhs| lhs * rhs)?;
+            }
+            OpCode::Div => self.binary(|lhs, rhs| lhs / rhs)?,
+            OpCode::Lt => {
+                self.binary(|lhs, rhs| Ok(Value::Int32(lhs.compare(rhs)?.is_lt() as i32)))?
+            }
+            OpCode::Lte => {
+                self.binary(|lhs, rhs| Ok(Value::Int32(lhs.compare(rhs)?.is_le() as i32)))?
+            }
+            OpCode::Gt => {
+                self.binary(|lhs, rhs| Ok(Value::Int32(lhs.compare(rhs)?.is_gt() as i32)))?
+            }
+            OpCode::Gte => {
+                self.binary(|lhs, rhs| Ok(Value::Int32(lhs.compare(rhs)?.is_ge() as i32)))?
+            }
+            OpCode::Eq => self.binary(|lhs, rhs| Ok(Value::Int32(lhs.equals(rhs) as i32)))?,
+            OpCode::Ne => self.binary(|lhs, rhs| Ok(Value::Int32(!lhs.equals(rhs) as i32)))?,
+            OpCode::If(..) => {
+                let value = self.pop_int()?;
+
+                let return_addr = self.read_isize();
+
+                if value == 0 {
+                    self.jmp(return_addr as usize)?;
+                }
+            }
+            OpCode::Else(..) => {
+                let return_addr = self.read_isize();
+                self.jmp(return_addr as usize)?;
+            }
+            OpCode::While => {
+                // nothing
+            }
+            OpCode::Do(..) => {
+                let value = self.pop_int()?;
+
+                let return_addr = self.read_isize();
+
+                if value == 0 {
+                    self.jmp(return_addr as usize)?;
+                }
+            }
+            OpCode::Dump => {
+                let value = self.pop()?;
+                self.write_line(&value.to_string())?;
+            }
+            OpCode::Halt => {
+                return Ok(false);
+            }
+            OpCode::Exit => {
+                self.exit_code = self.pop_int()?;
+                return Ok(false);
+            }
+            OpCode::LBrace => (),
+            OpCode::RBrace(..) => {
+                let return_addr = self.read_isize();
+
+                // only loop blocks jump back to their condition
+                if return_addr >= 0 {
+                    self.jmp(return_addr as usize)?;
+                }
+            }
+            OpCode::Break(..) | OpCode::Continue(..) => {
+                let return_addr = self.read_isize();
+                let unloop = self.read_isize();
+
+                let len = self.loop_stack.len();
+                let depth = len.saturating_sub(unloop as usize);
+
+                // list literals opened inside of the loops left behind are abandoned
+                if let Some(frame) = self.loop_stack.get(depth) {
+                    self.list_marks.truncate(frame.list_depth);
+                }
+
+                self.loop_stack.truncate(depth);
+                self.jmp(return_addr as usize)?;
+            }
+            OpCode::For(..) | OpCode::ForStep(..) => {
+                let return_addr = self.read_isize();
+
+                let frame = match (opcode, self.stack.last()) {
+                    (OpCode::For(..), Some(Value::List(..))) => {
+                        let items = self.pop_list()?;
+                        LoopFrame {
+                            index: 0,
+                            limit: items.len() as i32,
+                            step: 1,
+                            items: Some(items),
+                            list_depth: self.list_marks.len(),
+                        }
+                    }
+                    _ => {
+                        let step = match opcode {
+                            OpCode::ForStep(..) => self.pop_int()?,
+                            _ => 1,
+                        };
+                        let index = self.pop_int()?;
+                        let limit = self.pop_int()?;
+
+                        if step == 0 {
+                            return Err(Error::RuntimeError(
+                                "'for-step' requires a non-zero step".to_string(),
+                            ));
+                        }
+
+                        LoopFrame {
+                            index,
+                            limit,
+                            step,
+                            items: None,
+                            list_depth: self.list_marks.len(),
+                        }
+                    }
+                };
+
+                if frame.is_finished() {
+                    self.jmp(return_addr as usize)?;
+                } else {
+                    if let Some(items) = &frame.items {
+                        self.stack.push(items[0].clone());
+                    }
+
+                    self.loop_stack.push(frame);
+                }
+            }
+            OpCode::Next(..) => {
+                let return_addr = self.read_isize();
+
+                let Some(frame) = self.loop_stack.last_mut() else {
+                    return Err(Error::RuntimeError(
+                        "'next' can only be used inside of a counted loop".to_string(),
+                    ));
+                };
+
+                // a step past the end of the integer range also ends the loop
+                let next = frame.index.checked_add(frame.step);
+                if let Some(index) = next {
+                    frame.index = index;
+                }
+
+                if next.is_none() || frame.is_finished() {
+                    self.loop_stack.pop();
+                } else {
+                    if let Some(items) = &frame.items {
+                        let item = items[frame.index as usize].clone();
+                        self.stack.push(item);
+                    }
+
+                    self.jmp(return_addr as usize)?;
+                }
+            }
+            OpCode::Len => {
+                let len = match self.pop()? {
+                    Value::String(string) => string.chars().count(),
+                    Value::List(items) => items.len(),
+                    Value::Map(entries) => entries.len(),
+                    value => {
+                        return Err(Error::RuntimeError(format!(
+                            "expected a string, list or map but found '{}'",
+                            value.type_name()
+                        )));
+                    }
+                };
+
+                self.stack.push(Value::Int32(len as i32));
+            }
+            OpCode::Slice => {
+                let end = self.pop_int()?;
+                let start = self.pop_int()?;
+                let string = self.pop_string()?;
+
+                let len = string.chars().count() as i32;
+                if start < 0 || end < start || end > len {
+                    return Err(Error::RuntimeError(format!(
+                        "slice {start}..{end} is out of bounds for a string of length {len}"
+                    )));
+                }
+
+                let slice = string
+                    .chars()
+                    .skip(start as usize)
+                    .take((end - start) as usize)
+                    .collect::<String>();
+                self.allocate(slice.len())?;
+                self.stack.push(Value::String(slice));
+            }
+            OpCode::IndexOf => {
+                let needle = self.pop_string()?;
+                let string = self.pop_string()?;
+
+                // report the position in characters rather than bytes
+                let index = match string.find(&needle) {
+                    Some(byte_index) => string[..byte_index].chars().count() as i32,
+                    None => -1,
+                };
+                self.stack.push(Value::Int32(index));
+            }
+            OpCode::Split => {
+                let separator = self.pop_string()?;
+                let string = self.pop_string()?;
+
+                if separator.is_empty() {
+                    return Err(Error::RuntimeError(
+                        "cannot split a string with an empty separator".to_string(),
+                    ));
+                }
+
+                self.allocate(string.len())?;
+
+                let mut count = 0;
+                for part in string.split(&separator) {
+                    self.stack.push(Value::String(part.to_string()));
+                    count += 1;
+                }
+                self.stack.push(Value::Int32(count));
+            }
+            OpCode::Join => {
+                let separator = self.pop_string()?;
+                let count = self.pop_int()?;
+
+                if count < 0 || count as usize > self.stack.len() {
+                    return Err(Error::RuntimeError(format!(
+                        "cannot join {count} values, the stack only holds {}",
+                        self.stack.len()
+                    )));
+                }
+
+                let start = self.stack.len() - count as usize;
+                let separators = separator.len() * (count as usize).saturating_sub(1);
+                let text = self.stack[start..]
+                    .iter()
+                    .map(Value::display_len)
+                    .sum::<usize>();
+                self.allocate(text + separators)?;
+
+                let parts = self.stack.split_off(start);
+                let parts = parts.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                self.stack.push(Value::String(parts.join(&separator)));
+            }
+            OpCode::Upper => {
+                let string = self.pop_string()?;
+                self.allocate(string.len())?;
+                self.stack.push(Value::String(string.to_uppercase()));
+            }
+            OpCode::Lower => {
+                let string = self.pop_string()?;
+                self.allocate(string.len())?;
+                self.stack.push(Value::String(string.to_lowercase()));
+            }
+            OpCode::Trim => {
+                let string = self.pop_string()?;
+                self.allocate(string.trim().len())?;
+                self.stack.push(Value::String(string.trim().to_string()));
+            }
+            OpCode::CharAt => {
+                let index = self.pop_int()?;
+                let string = self.pop_string()?;
+
+                let c = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| string.chars().nth(index));
+
+                let Some(c) = c else {
+                    return Err(Error::RuntimeError(format!(
+                        "index {index} is out of bounds for a string of length {}",
+                        string.chars().count()
+                    )));
+                };
+
+                self.allocate(c.len_utf8())?;
+                self.stack.push(Value::String(c.to_string()));
+            }
+            OpCode::ToInt => {
+                let string = self.pop_string()?;
+
+                match string.trim().parse::<i32>() {
+                    Ok(int32) => self.stack.push(Value::Int32(int32)),
+                    Err(..) => {
+                        return Err(Error::RuntimeError(format!(
+                            "cannot convert '{string}' to an integer"
+                        )));
+                    }
+                }
+            }
+            OpCode::ToStr => {
+                let value = self.pop()?;
+                let string = value.to_string();
+                self.allocate(string.len())?;
+                self.stack.push(Value::String(string));
+            }
+            OpCode::Fmt => {
+                let format = self.pop_string()?;
+                let format = Format::parse(&format).map_err(Error::RuntimeError)?;
+
+                let count = format.placeholder_count();
+                if count > self.stack.len() {
+                    return Err(Error::RuntimeError(format!(
+                        "format string expects {count} values but the stack only holds {}",
+                        self.stack.len()
+                    )));
+                }
+
+                let start = self.stack.len() - count;
+                self.allocate(format.max_len(&self.stack[start..]))?;
+
+                let values = self.stack.split_off(start);
+                let string = format.format(&values)?;
+                self.stack.push(Value::String(string));
+            }
+            OpCode::ListStart => {
+                self.list_marks.push(self.stack.len());
+            }
+            OpCode::ListEnd => {
+                let Some(mark) = self.list_marks.pop() else {
+                    return Err(Error::RuntimeError(
+                        "']' does not close any list".to_string(),
+                    ));
+                };
+
+                if mark > self.stack.len() {
+                    return Err(Error::RuntimeError(
+                        "list literal consumed values from outside of its brackets".to_string(),
+                    ));
+                }
+
+                self.allocate((self.stack.len() - mark) * std::mem::size_of::<Value>())?;
+
+                let items = self.stack.split_off(mark);
+                self.stack.push(Value::List(Rc::new(items)));
+            }
+            OpCode::ListPush => {
+                let value = self.pop()?;
+                let mut items = self.pop_list()?;
+
+                self.allocate_copy(Rc::strong_count(&items) > 1, items.iter())?;
+                self.allocate(std::mem::size_of::<Value>())?;
+                Rc::make_mut(&mut items).push(value);
+                self.stack.push(Value::List(items));
+            }
+            OpCode::ListPop => {
+                let mut items = self.pop_list()?;
+
+                self.allocate_copy(Rc::strong_count(&items) > 1, items.iter())?;
+                let Some(value) = Rc::make_mut(&mut items).pop() else {
+                    return Err(Error::RuntimeError(
+                        "cannot pop from an empty list".to_string(),
+                    ));
+                };
+
+                self.stack.push(Value::List(items));
+                self.stack.push(value);
+            }
+            OpCode::ListGet => {
+                let index = self.pop_int()?;
+                let items = self.pop_list()?;
+
+                let index = Self::list_index(&items, index)?;
+                self.allocate(Self::copy_bytes(&items[index]))?;
+                self.stack.push(items[index].clone());
+            }
+            OpCode::ListSet => {
+                let value = self.pop()?;
+                let index = self.pop_int()?;
+                let mut items = self.pop_list()?;
+
+                let index = Self::list_index(&items, index)?;
+                self.allocate_copy(Rc::strong_count(&items) > 1, items.iter())?;
+                Rc::make_mut(&mut items)[index] = value;
+                self.stack.push(Value::List(items));
+            }
+            OpCode::MapNew => {
+                self.stack.push(Value::Map(Rc::new(BTreeMap::new())));
+            }
+            OpCode::MapInsert => {
+                let value = self.pop()?;
+                let key = self.pop()?.into_map_key()?;
+                let mut entries = self.pop_map()?;
+
+                let shared = Rc::strong_count(&entries) > 1;
+                self.allocate_copy(shared, entries.keys().chain(entries.values()))?;
+                self.allocate(2 * std::mem::size_of::<Value>())?;
+                Rc::make_mut(&mut entries).insert(key, value);
+                self.stack.push(Value::Map(entries));
+            }
+            OpCode::MapGetOr => {
+                let default = self.pop()?;
+                let key = self.pop()?.into_map_key()?;
+                let entries = self.pop_map()?;
+
+                let value = entries.get(&key).cloned().unwrap_or(default);
+                self.allocate(Self::copy_bytes(&value))?;
+                self.stack.push(value);
+            }
+            OpCode::MapRemove => {
+                let key = self.pop()?.into_map_key()?;
+                let mut entries = self.pop_map()?;
+
+                let shared = Rc::strong_count(&entries) > 1;
+                self.allocate_copy(shared, entries.keys().chain(entries.values()))?;
+                Rc::make_mut(&mut entries).remove(&key);
+                self.stack.push(Value::Map(entries));
+            }
+            OpCode::MapContains => {
+                let key = self.pop()?.into_map_key()?;
+                let entries = self.pop_map()?;
+
+                let contains = entries.contains_key(&key);
+                self.stack.push(Value::Int32(contains as i32));
+            }
+            OpCode::MapKeys => {
+                let entries = self.pop_map()?;
+
+                self.allocate_copy(true, entries.keys())?;
+                let keys = entries.keys().cloned().collect();
+                self.stack.push(Value::List(Rc::new(keys)));
+            }
+            OpCode::StructNew(..) => {
+                let type_id = self.read_isize() as usize;
+                let def = self.byte_code.structs[type_id].clone();
+
+                let field_count = def.fields.len();
+                if field_count > self.stack.len() {
+                    return Err(Error::RuntimeError(format!(
+                        "'{}' expects {field_count} fields but the stack only holds {}",
+                        def.name,
+                        self.stack.len()
+                    )));
+                }
+
+                self.allocate(field_count * std::mem::size_of::<Value>())?;
+
+                let fields = self.stack.split_off(self.stack.len() - field_count);
+                self.stack.push(Value::Struct(StructValue {
+                    type_id,
+                    def,
+                    fields: Rc::new(fields),
+                }));
+            }
+            OpCode::StructGet(..) => {
+                let type_id = self.read_isize() as usize;
+                let field = self.read_isize() as usize;
+
+                let value = self.pop_struct(type_id)?;
+                self.allocate(Self::copy_bytes(&value.fields[field]))?;
+                self.stack.push(value.fields[field].clone());
+            }
+            OpCode::StructSet(..) => {
+                let type_id = self.read_isize() as usize;
+                let field = self.read_isize() as usize;
+
+                let field_value = self.pop()?;
+                let mut value = self.pop_struct(type_id)?;
+
+                self.allocate_copy(Rc::strong_count(&value.fields) > 1, value.fields.iter())?;
+                Rc::make_mut(&mut value.fields)[field] = field_value;
+                self.stack.push(Value::Struct(value));
+            }
+            OpCode::Quote(..) => {
+                let return_addr = self.read_isize();
+
+                // the quotation body starts right after this instruction
+                self.stack.push(Value::Quotation(self.ip));
+                self.jmp(return_addr as usize)?;
+            }
+            OpCode::Return => {
+                let Some(frame) = self.return_stack.last_mut() else {
+                    return Err(Error::RuntimeError(
+                        "':]' reached outside of a quotation call".to_string(),
+                    ));
+                };
+
+                let next = match &mut frame.kind {
+                    FrameKind::Call => None,
+                    FrameKind::Times {
+                        quotation,
+                        remaining,
+                    } => {
+                        *remaining -= 1;
+                        (*remaining >= 0).then_some((*quotation, None))
+                    }
+                    FrameKind::Each {
+                        quotation,
+                        items,
+                        index,
+                    } => {
+                        *index += 1;
+                        items
+                            .get(*index)
+                            .map(|item| (*quotation, Some(item.clone())))
+                    }
+                    FrameKind::Map {
+                        quotation,
+                        items,
+                        index,
+                        results,
+                    } => {
+                        let Some(result) = self.stack.pop() else {
+                            return Err(Error::RuntimeError(
+                                "'map' quotation must leave a value on the stack".to_string(),
+                            ));
+                        };
+
+                        self.heap_bytes += std::mem::size_of::<Value>();
+                        results.push(result);
+                        *index += 1;
+                        items
+                            .get(*index)
+                            .map(|item| (*quotation, Some(item.clone())))
+                    }
+                };
+
+                match next {
+                    Some((quotation, item)) => {
+                        if let Some(item) = item {
+                            self.stack.push(item);
+                        }
+
+                        self.jmp(quotation)?;
+                    }
+                    None => {
+                        let frame = self.return_stack.pop().unwrap();
+
+                        if let FrameKind::Map { results, .. } = frame.kind {
+                            self.stack.push(Value::List(Rc::new(results)));
+                        }
+
+                        self.jmp(frame.return_addr)?;
+                    }
+                }
+            }
+            OpCode::Try(..) => {
+                let catch_addr = self.read_isize();
+
+                self.handlers.push(Handler {
+                    catch_addr: catch_addr as usize,
+                    stack_depth: self.stack.len(),
+                    loop_depth: self.loop_stack.len(),
+                    list_depth: self.list_marks.len(),
+                    return_depth: self.return_stack.len(),
+                });
+            }
+            OpCode::Catch(..) => {
+                // the try block finished without throwing so skip over the catch block
+                let return_addr = self.read_isize();
+
+                self.handlers.pop();
+                self.jmp(return_addr as usize)?;
+            }
+            OpCode::Assert(..) => {
+                let location = self.read_constant()?;
+                let value = self.pop_int()?;
+
+                if value == 0 {
+                    let at = self.assert_location(ip, location);
+                    return Err(Error::RuntimeError(format!("assertion failed{at}")));
+                }
+            }
+            OpCode::AssertEq(..) => {
+                let location = self.read_constant()?;
+                let rhs = self.pop()?;
+                let lhs = self.pop()?;
+
+                if !lhs.equals(&rhs) {
+                    let at = self.assert_location(ip, location);
+                    return Err(Error::RuntimeError(format!(
+                        "assertion failed{at}: '{lhs}' != '{rhs}'"
+                    )));
+                }
+            }
+            OpCode::ReadLine => {
+                let line = self.read_line()?;
+                let read = line.is_some();
+                self.allocate(line.as_ref().map_or(0, String::len))?;
+
+                self.stack.push(Value::String(line.unwrap_or_default()));
+                self.stack.push(Value::Int32(read as i32));
+            }
+            OpCode::ReadAll => {
+                let mut text = String::new();
+                self.input
+                    .read_to_string(&mut text)
+                    .map_err(|err| Error::RuntimeError(format!("failed to read input: {err}")))?;
+                let read = !text.is_empty();
+
+                self.allocate(text.len())?;
+                self.stack.push(Value::String(text));
+                self.stack.push(Value::Int32(read as i32));
+            }
+            OpCode::ReadInt => match self.read_line()? {
+                Some(line) => {
+                    let Ok(int32) = line.trim().parse::<i32>() else {
+                        return Err(Error::RuntimeError(format!(
+                            "expected an integer but read '{line}'"
+                        )));
+                    };
+
+                    self.stack.push(Value::Int32(int32));
+                    self.stack.push(Value::Int32(1));
+                }
+                None => {
+                    self.stack.push(Value::Int32(0));
+                    self.stack.push(Value::Int32(0));
+                }
+            },
+            OpCode::Argc => {
+                self.stack.push(Value::Int32(self.args.len() as i32));
+            }
+            OpCode::Argv => {
+                let index = self.pop_int()?;
+
+                let Some(arg) = usize::try_from(index).ok().and_then(|i| self.args.get(i)) else {
+                    return Err(Error::RuntimeError(format!(
+                        "argument index {index} is out of range for {} arguments",
+                        self.args.len()
+                    )));
+                };
+
+                let arg = arg.clone();
+                self.allocate(arg.len())?;
+                self.stack.push(Value::String(arg));
+            }
+            OpCode::GetEnv => {
+                let name = self.pop_string()?;
+
+                // pushes the value and whether the variable is set, like the read words
+                let value = std::env::var(&name).ok();
+                let found = value.is_some();
+                self.allocate(value.as_ref().map_or(0, String::len))?;
+
+                self.stack.push(Value::String(value.unwrap_or_default()));
+                self.stack.push(Value::Int32(found as i32));
+            }
+            OpCode::Open => {
+                let path = self.pop_string()?;
+                let policy = &options.file_policy;
+
+                // a file can be opened for either reading or writing
+                let resolved = policy
+                    .check(&path, Access::Read)
+                    .or_else(|err| policy.check(&path, Access::Write).map_err(|_| err))?;
+
+                let file = resolved.display().to_string();
+                self.allocate(file.len())?;
+                self.stack.push(Value::File(file));
+            }
+            OpCode::ReadFile => {
+                let (path, resolved) = self.pop_path(&options.file_policy, Access::Read)?;
+
+                // the size on disk is checked before the file is read into memory
+                if let Ok(metadata) = std::fs::metadata(&resolved) {
+                    self.allocate(metadata.len() as usize)?;
+                }
+
+                let contents = std::fs::read_to_string(resolved).map_err(|err| {
+                    Error::RuntimeError(format!("failed to read '{path}': {err}"))
+                })?;
+
+                self.stack.push(Value::String(contents));
+            }
+            OpCode::WriteFile | OpCode::AppendFile => {
+                let (path, resolved) = self.pop_path(&options.file_policy, Access::Write)?;
+                let contents = self.pop_string()?;
+
+                let append = opcode == OpCode::AppendFile;
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(resolved)
+                    .and_then(|mut file| file.write_all(contents.as_bytes()))
+                    .map_err(|err| {
+                        Error::RuntimeError(format!("failed to write '{path}': {err}"))
+                    })?;
+            }
+            OpCode::Exists => {
+                let (_, resolved) = self.pop_path(&options.file_policy, Access::Read)?;
+                self.stack.push(Value::Int32(resolved.exists() as i32));
+            }
+            OpCode::Delete => {
+                let (path, resolved) = self.pop_path(&options.file_policy, Access::Write)?;
+
+                std::fs::remove_file(resolved).map_err(|err| {
+                    Error::RuntimeError(format!("failed to delete '{path}': {err}"))
+                })?;
+            }
+            OpCode::CallNative(..) => {
+                let index = self.read_isize() as usize;
+                let native = &self.natives[self.linked_natives[index]];
+
+                if self.stack.len() < native.pops {
+                    return Err(Error::RuntimeError(format!(
+                        "native function '{}' takes {} values but the stack only holds {}",
+                        native.name,
+                        native.pops,
+                        self.stack.len()
+                    )));
+                }
+
+                let expected = self.stack.len() - native.pops + native.pushes;
+                (native.function)(&mut self.stack)?;
+
+                if self.stack.len() != expected {
+                    return Err(Error::RuntimeError(format!(
+                        "native function '{}' did not keep to its stack effect of {} in and {} out",
+                        native.name, native.pops, native.pushes
+                    )));
+                }
+            }
+            OpCode::Throw => {
+                let value = self.pop()?;
+                return Err(Error::Thrown(value, ip));
+            }
+            OpCode::Call => {
+                let quotation = self.pop_quotation()?;
+                self.call(quotation, FrameKind::Call)?;
+            }
+            OpCode::Times => {
+                let count = self.pop_int()?;
+                let quotation = self.pop_quotation()?;
+
+                if count > 0 {
+                    let remaining = count - 1;
+                    let kind = FrameKind::Times {
+                        quotation,
+                        remaining,
+                    };
+                    self.call(quotation, kind)?;
+                }
+            }
+            OpCode::Each | OpCode::MapList => {
+                let quotation = self.pop_quotation()?;
+                let items = self.pop_list()?;
+
+                match items.first().cloned() {
+                    Some(first) => {
+                        let kind = if opcode == OpCode::Each {
+                            FrameKind::Each {
+                                quotation,
+                                items,
+                                index: 0,
+                            }
+                        } else {
+                            FrameKind::Map {
+                                quotation,
+                                items,
+                                index: 0,
+                                results: vec![],
+                            }
+                        };
+
+                        self.stack.push(first);
+                        self.call(quotation, kind)?;
+                    }
+                    None if opcode == OpCode::MapList => {
+                        self.stack.push(Value::List(Rc::new(vec![])));
+                    }
+                    None => (),
+                }
+            }
+            OpCode::I | OpCode::J => {
+                let depth = if opcode == OpCode::I { 1 } else { 2 };
+
+                if self.loop_stack.len() < depth {
+                    let err = if depth == 1 {
+                        "'i' can only be used inside of a counted loop"
+                    } else {
+                        "'j' can only be used inside of a nested counted loop"
+                    };
+
+                    return Err(Error::RuntimeError(err.to_string()));
+                }
+
+                let frame = &self.loop_stack[self.loop_stack.len() - depth];
+                self.stack.push(Value::Int32(frame.index));
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Hands a runtime error to the innermost 'catch' block, errors
+    /// that no handler is waiting for are returned to the caller
+    fn unwind(&mut self, err: Error) -> RuntimeResult<()> {
+        let payload = match err {
+            Error::Thrown(ref value, _) => value.clone(),
+            Error::RuntimeError(ref message) => Value::String(message.clone()),
+            _ => return Err(err),
+        };
+
+        let Some(handler) = self.handlers.pop() else {
+            return Err(err);
+        };
+
+        self.stack.truncate(handler.stack_depth);
+        self.loop_stack.truncate(handler.loop_depth);
+        self.list_marks.truncate(handler.list_depth);
+        self.return_stack.truncate(handler.return_depth);
+
+        self.stack.push(payload);
+        self.jmp(handler.catch_addr)
+    }
+
+    pub fn load_binary(&mut self, binary: String) -> RuntimeResult<()> {
+        if !Path::new(&binary).exists() {
+            return Err(Error::InvalidFilepath(binary));
+        }
+
+        if !binary.ends_with(".nar") && !binary.ends_with(".out") {
+            let ext = utils::extension_from_path(&binary);
+            return Err(Error::InvalidExtension(ext));
+        }
+
+        let path = utils::filename_from_path(binary.as_str());
+        println!("{} '{path}'", "Loading Binary".green());
+
+        match std::fs::read(&binary) {
+            Ok(bytes) => self.load_bytes(bytes),
+            Err(..) => Err(Error::CorruptedBinary),
+        }
+    }
+
+    /// Makes `function` callable from nere code that declares `extern name`,
+    /// it takes `pops` values off of the stack and leaves `pushes` in their place
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        pops: usize,
+        pushes: usize,
+        function: impl Fn(&mut Stack) -> Result<(), Error> + 'static,
+    ) {
+        let native = Native {
+            name: name.to_string(),
+            pops,
+            pushes,
+            function: Box::new(function),
+        };
+
+        match self.natives.iter().position(|native| native.name == name) {
+            Some(index) => self.natives[index] = native,
+            None => self.natives.push(native),
+        }
+    }
+
+    /// Sets the arguments a program reads with 'argc' and 'argv'
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Replaces stdin as the source of 'read-line', 'read-all' and 'read-int'
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = input;
+    }
+
+    /// Sends the stack trace somewhere other than the program output
+    pub fn set_trace_output(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    /// Redirects everything the program prints, e.g. into a buffer when embedding
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Loads a program from the bytes of a compiled binary
+    pub fn load_bytes(&mut self, mut bytes: Vec<u8>) -> RuntimeResult<()> {
+        if bytes.len() < 8 {
+            return Err(Error::CorruptedBinary);
+        }
+
+        let halt_index_bytes: [u8; 8] = bytes.drain(0..=7).collect::<Vec<u8>>().try_into().unwrap();
+        let halt_index = usize::from_ne_bytes(halt_index_bytes);
+
+        if halt_index >= bytes.len() {
+            return Err(Error::CorruptedBinary);
+        }
+
+        let mut section_bytes = bytes.drain(halt_index + 1..).collect::<Vec<u8>>();
+
+        self.byte_code.bytes = bytes;
+
+        self.load_constants(&mut section_bytes)?;
+        self.load_structs(&mut section_bytes)?;
+        self.load_natives(&mut section_bytes)?;
+
+        // stripped binaries end after the natives
+        if !section_bytes.is_empty() {
+            self.load_debug_info(&mut section_bytes)?;
+        }
+
+        self.byte_code
+            .validate()
+            .map_err(|_| Error::CorruptedBinary)
+    }
+
+    /// Checks that every native the program declares is registered
+    fn link_natives(&mut self) -> RuntimeResult<()> {
+        self.linked_natives.clear();
+
+        for name in self.byte_code.natives.iter() {
+            match self.natives.iter().position(|native| &native.name == name) {
+                Some(index) => self.linked_natives.push(index),
+                None => return Err(Error::UnresolvedNative(name.clone())),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next line without its line ending, None at the end of the input
+    fn read_line(&mut self) -> RuntimeResult<Option<String>> {
+        let mut line = String::new();
+        let count = self
+            .input
+            .read_line(&mut line)
+            .map_err(|err| Error::RuntimeError(format!("failed to read input: {err}")))?;
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        if line.ends_with('\n') {
+            line.pop();
+
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+
+        Ok(Some(line))
+    }
+
+    fn write_line(&mut self, line: &str) -> RuntimeResult<()> {
+        writeln!(self.output, "{line}")
+            .map_err(|err| Error::RuntimeError(format!("failed to write output: {err}")))
+    }
+
+    fn pop(&mut self) -> RuntimeResult<Value> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => Err(Error::RuntimeError("stack underflow".to_string())),
+        }
+    }
+
+    /// Replaces the top two values with the result of `operation`, they are
+    /// left on the stack when it fails so that the backtrace shows them
+    fn binary(
+        &mut self,
+        operation: impl FnOnce(&Value, &Value) -> RuntimeResult<Value>,
+    ) -> RuntimeResult<()> {
+        let [.., lhs, rhs] = self.stack.as_slice() else {
+            return Err(Error::RuntimeError("stack underflow".to_string()));
+        };
+
+        let result = operation(lhs, rhs)?;
+        self.stack.truncate(self.stack.len() - 2);
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// The error for an operand of the wrong kind, which goes back on the
+    /// stack so that the backtrace shows it
+    fn wrong_kind(&mut self, expected: &str, value: Value) -> Error {
+        let err = Error::RuntimeError(format!(
+            "expected {expected} but found '{}'",
+            value.type_name()
+        ));
+
+        self.stack.push(value);
+        err
+    }
+
+    fn pop_int(&mut self) -> RuntimeResult<i32> {
+        match self.pop()? {
+            value if value.is_integer() => Ok(value.as_i32_implicit()),
+            value => Err(self.wrong_kind("an integer", value)),
+        }
+    }
+
+    fn pop_string(&mut self) -> RuntimeResult<String> {
+        match self.pop()? {
+            Value::String(string) => Ok(string),
+            value => Err(self.wrong_kind("a string", value)),
+        }
+    }
+
+    fn pop_list(&mut self) -> RuntimeResult<Rc<Vec<Value>>> {
+        match self.pop()? {
+            Value::List(items) => Ok(items),
+            value => Err(self.wrong_kind("a list", value)),
+        }
+    }
+
+    /// Pops a path or a file from 'open' and checks it against the file policy
+    fn pop_path(
+        &mut self,
+        policy: &FilePolicy,
+        access: Access,
+    ) -> RuntimeResult<(String, PathBuf)> {
+        let path = match self.pop()? {
+            Value::String(path) | Value::File(path) => path,
+            value => return Err(self.wrong_kind("a path or a file", value)),
+        };
+
+        let resolved = policy.check(&path, access)?;
+        Ok((path, resolved))
+    }
+
+    fn pop_quotation(&mut self) -> RuntimeResult<usize> {
+        match self.pop()? {
+            Value::Quotation(ip) => Ok(ip),
+            value => Err(self.wrong_kind("a quotation", value)),
+        }
+    }
+
+    fn call(&mut self, quotation: usize, kind: FrameKind) -> RuntimeResult<()> {
+        self.return_stack.push(Frame {
+            return_addr: self.ip,
+            entry: quotation,
+            kind,
+        });
+
+        self.jmp(quotation)
+    }
+
+    fn pop_map(&mut self) -> RuntimeResult<Rc<BTreeMap<Value, Value>>> {
+        match self.pop()? {
+            Value::Map(entries) => Ok(entries),
+            value => Err(self.wrong_kind("a map", value)),
+        }
+    }
+
+    fn pop_struct(&mut self, type_id: usize) -> RuntimeResult<StructValue> {
+        match self.pop()? {
+            Value::Struct(value) if value.type_id == type_id => Ok(value),
+            value => {
+                let found = match &value {
+                    Value::Struct(other) => other.def.name.as_str(),
+                    _ => value.type_name(),
+                };
+
+                let err = Error::RuntimeError(format!(
+                    "expected a '{}' but found '{found}'",
+                    self.byte_code.structs[type_id].name
+                ));
+
+                self.stack.push(value);
+                Err(err)
+            }
+        }
+    }
+
+    fn list_index(items: &[Value], index: i32) -> RuntimeResult<usize> {
+        match usize::try_from(index) {
+            Ok(index) if index < items.len() => Ok(index),
+            _ => Err(Error::RuntimeError(format!(
+                "index {index} is out of bounds for a list of length {}",
+                items.len()
+            ))),
+        }
+    }
+
+    fn advance(&mut self) -> usize {
+        self.ip += 1;
+        self.ip - 1
+    }
+
+    fn jmp(&mut self, to: usize) -> RuntimeResult<()> {
+        let len = self.byte_code.bytes.len();
+        if to >= len {
+            return Err(Error::SegFault(
+                self.ip,
+                "attempting to access restricted memory".to_string(),
+            ));
+        }
+
+        self.ip = to;
+        Ok(())
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.ip >= self.byte_code.bytes.len()
+    }
+
+    fn load_constants(&mut self, constant_bytes: &mut Vec<u8>) -> RuntimeResult<()> {
+        let count = Self::take_usize(constant_bytes)?;
+
+        for _ in 0..count {
+            let constant = Self::load_constant(constant_bytes)?;
+            self.byte_code.constants.push(constant);
+        }
+
+        Ok(())
+    }
+
+    fn load_structs(&mut self, struct_bytes: &mut Vec<u8>) -> RuntimeResult<()> {
+        let count = Self::take_usize(struct_bytes)?;
+
+        for _ in 0..count {
+            let field_count = Self::take_usize(struct_bytes)?;
+            let name = Self::take_string(struct_bytes)?;
+
+            // the count comes from the file, so the fields are only allocated as they are read
+            let mut fields = vec![];
+            for _ in 0..field_count {
+                fields.push(Self::take_string(struct_bytes)?);
+            }
+
+            self.byte_code
+                .structs
+                .push(Rc::new(StructDef { name, fields }));
+        }
+
+        Ok(())
+    }
+
+    fn load_natives(&mut self, native_bytes: &mut Vec<u8>) -> RuntimeResult<()> {
+        let count = Self::take_usize(native_bytes)?;
+
+        for _ in 0..count {
+            let name = Self::take_string(native_bytes)?;
+            self.byte_code.natives.push(name);
+        }
+
+        Ok(())
+    }
+
+    fn load_debug_info(&mut self, debug_bytes: &mut Vec<u8>) -> RuntimeResult<()> {
+        let path_count = Self::take_usize(debug_bytes)?;
+
+        let mut paths = vec![];
+        for _ in 0..path_count {
+            paths.push(Self::take_string(debug_bytes)?);
+        }
+
+        let count = Self::take_usize(debug_bytes)?;
+        let mut debug_info = DebugInfo::default();
+
+        for _ in 0..count {
+            let offset = Self::take_usize(debug_bytes)?;
+            let path_index = Self::take_usize(debug_bytes)?;
+            let line = Self::take_usize(debug_bytes)?;
+            let column = Self::take_usize(debug_bytes)?;
+            let symbol = Self::take_string(debug_bytes)?;
+
+            let Some(path) = paths.get(path_index) else {
+                return Err(Error::CorruptedBinary);
+            };
+
+            debug_info.lines.push(LineEntry {
+                offset,
+                location: Location {
+                    path: path.clone(),
+                    line,
+                    column,
+                },
+                symbol,
+            });
+        }
+
+        self.byte_code.debug_info = Some(debug_info);
+        Ok(())
+    }
+
+    fn take_bytes<const N: usize>(bytes: &mut Vec<u8>) -> RuntimeResult<[u8; N]> {
+        if bytes.len() < N {
+            return Err(Error::CorruptedBinary);
+        }
+
+        let mut taken = [0; N];
+        taken.copy_from_slice(&bytes[..N]);
+        bytes.drain(..N);
+        Ok(taken)
+    }
+
+    fn take_usize(bytes: &mut Vec<u8>) -> RuntimeResult<usize> {
+        Self::take_bytes::<8>(bytes).map(usize::from_ne_bytes)
+    }
+
+    fn take_string(bytes: &mut Vec<u8>) -> RuntimeResult<String> {
+        let len = Self::take_usize(bytes)?;
+        if bytes.len() < len {
+            return Err(Error::CorruptedBinary);
+        }
+
+        let str_bytes = bytes.drain(0..len).collect::<Vec<u8>>();
+        String::from_utf8(str_bytes).map_err(|_| Error::InvalidUTF8String)
+    }
+
+    fn load_constant(constant_bytes: &mut Vec<u8>) -> RuntimeResult<Value> {
+        let [constant_type] = Self::take_bytes::<1>(constant_bytes)?;
+
+        match constant_type {
+            0 => {
+                let bytes = Self::take_bytes::<4>(constant_bytes)?;
+                Ok(Value::Int32(i32::from_ne_bytes(bytes)))
+            }
+            1 => {
+                let bytes = Self::take_bytes::<4>(constant_bytes)?;
+                Ok(Value::UInt32(u32::from_ne_bytes(bytes)))
+            }
+            2 => Ok(Value::String(Self::take_string(constant_bytes)?)),
+            3 => {
+                let len = Self::take_usize(constant_bytes)?;
+
+                // the length comes from the file, so the items are only
+                // allocated as they are read
+                let mut items = vec![];
+                for _ in 0..len {
+                    items.push(Self::load_constant(constant_bytes)?);
+                }
+
+                Ok(Value::List(Rc::new(items)))
+            }
+            _ => Err(Error::CorruptedBinary),
+        }
+    }
+
+    fn read_constant(&mut self) -> RuntimeResult<Value> {
+        let bytes: [u8; 8] = self.byte_code.bytes[self.ip..self.ip + 8]
+            .try_into()
+            .unwrap();
+        let constant_index = usize::from_ne_bytes(bytes);
+        self.ip += 8;
+
+        match self.byte_code.constants.get(constant_index) {
+            Some(constant) => Ok(constant.clone()),
+            None => Err(Error::CorruptedBinary),
+        }
+    }
+
+    fn read_isize(&mut self) -> isize {
+        let bytes: [u8; 8] = self.byte_code.bytes[self.ip..self.ip + 8]
+            .try_into()
+            .unwrap();
+        let value = isize::from_ne_bytes(bytes);
+        self.ip += 8;
+        value
+    }
+}
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,57 @@
+//! Helpers shared by the integration tests, each test crate only uses some of them
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use nere_internal::compiler::Compiler;
+
+/// Collects what a program writes so it can be read back after the run
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+}
+
+/// Compiles the program at `path`, failing the test with the compile error
+pub fn compile(path: &str) -> Vec<u8> {
+    let compiler = Compiler::default();
+
+    match compiler
+        .scan_file(path)
+        .and_then(|tokens| compiler.compile_tokens(tokens))
+    {
+        Ok(bytes) => bytes,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+/// Collects every '.nere' program under `dir` into `files`
+pub fn discover(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            discover(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "nere") {
+            files.push(path);
+        }
+    }
+}
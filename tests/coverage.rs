@@ -0,0 +1,91 @@
+//! Runs a program with one branch taken and one skipped and checks the
+//! coverage it reports, both as json and as lcov records
+
+mod common;
+
+use nere_internal::{
+    coverage::CoverageReport,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+};
+
+const PROGRAM: &str = "test/coverage/branch.nere";
+
+fn run_with_coverage() -> CoverageReport {
+    let bytes = common::compile(PROGRAM);
+
+    let mut vm = VirtualMachine::default();
+    vm.set_output(Box::new(std::io::sink()));
+
+    let options = RuntimeOptions {
+        coverage: true,
+        ..Default::default()
+    };
+
+    if let Err(err) = vm.load_bytes(bytes).and_then(|_| vm.execute(&options)) {
+        panic!("{err}");
+    }
+
+    CoverageReport::new(vm.coverage().unwrap(), vm.byte_code())
+}
+
+#[test]
+fn lcov_records_lines_and_branches() {
+    let lcov = run_with_coverage().lcov();
+
+    let expected = [
+        "TN:",
+        &format!("SF:{PROGRAM}"),
+        // the first 'if' was entered once, the second one skipped once
+        "BRDA:2,9,0,1",
+        "BRDA:2,9,1,0",
+        "BRDA:5,47,0,0",
+        "BRDA:5,47,1,1",
+        "BRF:4",
+        "BRH:2",
+        "DA:2,1",
+        "DA:3,1",
+        "DA:4,1",
+        "DA:5,1",
+        "DA:6,0",
+        "DA:7,0",
+        "LF:6",
+        "LH:4",
+        "end_of_record",
+    ];
+
+    assert_eq!(lcov.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn json_counts_hits_and_branches() {
+    let report = run_with_coverage();
+    let json = serde_json::to_string(&report).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    let branches = report["branches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|branch| {
+            (
+                branch["offset"].as_u64().unwrap(),
+                branch["taken"].as_u64().unwrap(),
+                branch["skipped"].as_u64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(branches, [(9, 1, 0), (47, 0, 1)]);
+
+    // every instruction of the skipped block is in the report with no hits
+    let hits = |line: u64| {
+        report["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|instruction| instruction["location"]["line"] == line)
+            .map(|instruction| instruction["hits"].as_u64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(hits(3), [1, 1]);
+    assert_eq!(hits(6), [0, 0]);
+}
@@ -0,0 +1,97 @@
+//! Drives the debugger with a script of commands and checks what it shows
+
+mod common;
+
+use std::{
+    io::{Cursor, Write},
+    process::{Command, Stdio},
+};
+
+use common::SharedBuffer;
+use nere_internal::{
+    debugger::Debugger,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+};
+
+const PROGRAM: &str = "target/debugger/add.nere";
+
+#[test]
+fn scripted_session_breaks_steps_and_shows_the_stack() {
+    std::fs::create_dir_all("target/debugger").unwrap();
+    std::fs::write(PROGRAM, "1 2 +\n.\n").unwrap();
+
+    let bytes = common::compile(PROGRAM);
+
+    let program_output = SharedBuffer::default();
+    let mut vm = VirtualMachine::default();
+    vm.set_output(Box::new(program_output.clone()));
+
+    let script = "break line 2\nstack\ncontinue\nstack\nstep\nstack\ncontinue\n";
+    let output = SharedBuffer::default();
+    let mut debugger = Debugger::new(Box::new(Cursor::new(script)), Box::new(output.clone()));
+
+    let result = vm
+        .load_bytes(bytes)
+        .and_then(|_| vm.execute_with_hook(&RuntimeOptions::default(), &mut debugger));
+
+    match result {
+        Ok(code) => assert_eq!(code, 0),
+        Err(err) => panic!("{err}"),
+    }
+
+    let expected = [
+        format!("<{PROGRAM}:1:1> '1'"),
+        "0000 [Push] 0000 '1'".to_string(),
+        "(ndb) breakpoint set at 0019".to_string(),
+        "(ndb) <empty>".to_string(),
+        // 'continue' runs the push, push and add before the breakpoint on line 2
+        "(ndb) breakpoint at 0019".to_string(),
+        format!("<{PROGRAM}:2:1> '.'"),
+        "0019 [Dump] ".to_string(),
+        "(ndb)    0: 3".to_string(),
+    ];
+
+    let text = output.text();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines[..expected.len()], expected, "{text}");
+
+    // 'step' runs the dump and stops at the halt with an empty stack
+    assert!(
+        text.ends_with("0020 [Halt] \n(ndb) <empty>\n(ndb) "),
+        "{text}"
+    );
+
+    assert_eq!(program_output.text(), "3\n");
+}
+
+#[test]
+fn program_reads_stdin_between_commands() {
+    std::fs::create_dir_all("target/debugger").unwrap();
+    std::fs::write("target/debugger/read.nere", "read-line . .\n").unwrap();
+
+    let bytes = common::compile("target/debugger/read.nere");
+    std::fs::write("target/debugger/read.out", bytes).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nere"))
+        .args(["debug", "target/debugger/read.out"])
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the command and the line the program reads come from the same stdin
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"continue\nhello\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("hello\n"), "{stdout}");
+    assert!(stdout.contains("program exited with code 0"), "{stdout}");
+}
@@ -0,0 +1,161 @@
+//! Runs every program in `test/` and compares what it prints with the
+//! `.expected` file next to it, set `NERE_BLESS=1` to write new expectations.
+//! A `.stdin` file next to a program is fed to it as its input and
+//! the words of an `.args` file are passed as its arguments. Programs may
+//! read from `test/` and read and write inside of `target/golden/`, and
+//! call the native functions registered in `register_natives`
+
+mod common;
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use common::SharedBuffer;
+use nere_internal::{
+    compiler::Compiler,
+    file_policy::FilePolicy,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+    Error, Value,
+};
+
+const BLESS_VAR: &str = "NERE_BLESS";
+const SCRATCH_DIR: &str = "target/golden";
+/// Keeps a program that loops forever from hanging the test run
+const FUEL: u64 = 1_000_000;
+
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+impl Outcome {
+    fn render(&self) -> String {
+        format!(
+            "exit code: {}\n--- stdout ---\n{}--- stderr ---\n{}",
+            self.exit_code, self.stdout, self.stderr
+        )
+    }
+}
+
+fn register_natives(vm: &mut VirtualMachine) {
+    vm.register_native("host-name", 0, 1, |stack| {
+        stack.push(Value::String("golden".to_string()));
+        Ok(())
+    });
+
+    vm.register_native("host-add", 2, 1, |stack| {
+        let rhs = stack.pop().unwrap();
+        let lhs = stack.pop().unwrap();
+        stack.push((lhs + rhs)?);
+        Ok(())
+    });
+
+    vm.register_native("host-fail", 0, 0, |_| {
+        Err(Error::RuntimeError("the host refused".to_string()))
+    });
+
+    // declares one value out but leaves the stack untouched
+    vm.register_native("host-broken", 0, 1, |_| Ok(()));
+}
+
+fn run_program(path: &Path) -> Outcome {
+    let output = SharedBuffer::default();
+
+    // every program starts with an empty scratch directory
+    let _ = std::fs::remove_dir_all(SCRATCH_DIR);
+    std::fs::create_dir_all(SCRATCH_DIR).unwrap();
+    let input = std::fs::read(path.with_extension("stdin")).unwrap_or_default();
+    let args = std::fs::read_to_string(path.with_extension("args"))
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let result = (|| -> Result<i32, Error> {
+        let compiler = Compiler::default();
+        let tokens = compiler.scan_file(&path.display().to_string())?;
+        let bytes = compiler.compile_tokens(tokens)?;
+
+        let mut vm = VirtualMachine::default();
+        register_natives(&mut vm);
+        vm.set_output(Box::new(output.clone()));
+        vm.set_input(Box::new(Cursor::new(input)));
+        vm.set_args(args);
+        vm.load_bytes(bytes)?;
+        let options = RuntimeOptions {
+            file_policy: FilePolicy {
+                read: vec![PathBuf::from("test"), PathBuf::from(SCRATCH_DIR)],
+                write: vec![PathBuf::from(SCRATCH_DIR)],
+            },
+            fuel: Some(FUEL),
+            max_stack: Some(1_000),
+            max_return_stack: Some(100),
+            max_heap_bytes: Some(64 * 1024),
+            ..Default::default()
+        };
+
+        vm.execute(&options)
+    })();
+
+    let (stderr, exit_code) = match result {
+        Ok(code) => (String::new(), code),
+        Err(err) => (format!("{err}\n"), err.exit_code()),
+    };
+
+    let stdout = output.text();
+
+    Outcome {
+        stdout,
+        stderr,
+        exit_code,
+    }
+}
+
+#[test]
+fn golden_outputs() {
+    colored::control::set_override(false);
+
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+
+    let mut programs = vec![];
+    common::discover(Path::new("test"), &mut programs);
+    programs.sort();
+
+    assert!(!programs.is_empty(), "no programs found in 'test/'");
+
+    let mut failures = vec![];
+
+    for program in programs.iter() {
+        let expected_path = program.with_extension("expected");
+        let actual = run_program(program).render();
+
+        if bless {
+            std::fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{}\n===== expected =====\n{expected}===== actual =====\n{actual}",
+                program.display()
+            )),
+            Err(..) => failures.push(format!(
+                "{}: missing '{}', run with {BLESS_VAR}=1 to create it",
+                program.display(),
+                expected_path.display()
+            )),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} programs did not match their expected output:\n\n{}",
+        failures.len(),
+        programs.len(),
+        failures.join("\n")
+    );
+}
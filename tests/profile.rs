@@ -0,0 +1,49 @@
+//! Profiles a program that calls a quotation from inside of another one
+//! and checks the collapsed call stacks written for flame graphs
+
+mod common;
+
+use nere_internal::virtual_machine::{RuntimeOptions, VirtualMachine};
+
+const PROGRAM: &str = "target/profile/nested.nere";
+
+#[test]
+fn collapsed_stacks_nest_quotation_calls() {
+    std::fs::create_dir_all("target/profile").unwrap();
+    std::fs::write(PROGRAM, "[: [: 1. :] 2 times :] call\n").unwrap();
+
+    let bytes = common::compile(PROGRAM);
+
+    let mut vm = VirtualMachine::default();
+    vm.set_output(Box::new(std::io::sink()));
+
+    let options = RuntimeOptions {
+        profile: true,
+        ..Default::default()
+    };
+
+    if let Err(err) = vm.load_bytes(bytes).and_then(|_| vm.execute(&options)) {
+        panic!("{err}");
+    }
+
+    let stacks = vm.profile().unwrap().collapsed_stacks(vm.byte_code());
+
+    let outer = "main;quotation <target/profile/nested.nere:1:2>";
+    let inner = format!("{outer};quotation <target/profile/nested.nere:1:5>");
+
+    // the inner quotation runs twice, so each of its instructions counts 2
+    let expected = [
+        "main;call <target/profile/nested.nere:1:27> 1".to_string(),
+        "main;halt <target/profile/nested.nere:2:0> 1".to_string(),
+        format!("{outer};push <target/profile/nested.nere:1:13> 1"),
+        format!("{inner};dump <target/profile/nested.nere:1:8> 2"),
+        format!("{inner};push <target/profile/nested.nere:1:7> 2"),
+        format!("{inner};return <target/profile/nested.nere:1:11> 2"),
+        format!("{outer};quote <target/profile/nested.nere:1:5> 1"),
+        format!("{outer};return <target/profile/nested.nere:1:22> 1"),
+        format!("{outer};times <target/profile/nested.nere:1:19> 1"),
+        "main;quote <target/profile/nested.nere:1:2> 1".to_string(),
+    ];
+
+    assert_eq!(stacks, expected);
+}
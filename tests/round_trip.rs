@@ -0,0 +1,202 @@
+//! Compiles every program in `test/`, disassembles it and assembles the
+//! listing again, the binary that comes out has to match the compiled one.
+//! Assembly and binaries with operands that point nowhere are rejected, as
+//! are binaries that are cut off or claim more data than they hold.
+
+mod common;
+
+use std::path::Path;
+
+use nere_internal::{
+    assembler::Assembler,
+    compiler::Compiler,
+    disassembler::Disassembler,
+    virtual_machine::{RuntimeOptions, VirtualMachine},
+    OpCode,
+};
+
+#[test]
+fn assembly_round_trips() {
+    let mut programs = vec![];
+    common::discover(Path::new("test"), &mut programs);
+    programs.sort();
+
+    let mut checked = 0;
+
+    for program in programs.iter() {
+        for strip in [false, true] {
+            let compiler = Compiler {
+                strip,
+                ..Default::default()
+            };
+
+            // programs that fail to compile are covered by the golden tests
+            let Ok(bytes) = compiler
+                .scan_file(&program.display().to_string())
+                .and_then(|tokens| compiler.compile_tokens(tokens))
+            else {
+                continue;
+            };
+
+            let mut vm = VirtualMachine::default();
+            if let Err(err) = vm.load_bytes(bytes.clone()) {
+                panic!("{}: {err}", program.display());
+            }
+
+            let assembly = Disassembler::assembly(vm.byte_code());
+            let assembled = Assembler::assemble(&assembly, "round_trip.nasm")
+                .unwrap_or_else(|err| panic!("{}:\n{err}\n{assembly}", program.display()));
+
+            assert!(
+                assembled == bytes,
+                "{} (strip: {strip}) did not assemble into the same bytes:\n{assembly}",
+                program.display()
+            );
+
+            checked += 1;
+        }
+    }
+
+    assert!(checked > 0, "no programs found in 'test/'");
+}
+
+#[test]
+fn assembler_rejects_bad_operands() {
+    let programs = [
+        ("push 5", "there is no constant 5"),
+        (
+            ".const 0 i32 1\npush 0\nif 3",
+            "jump to 3 does not land on an instruction",
+        ),
+        ("else -1", "jump to -1 does not land on an instruction"),
+        (
+            ".struct \"Point\" \"x\"\nstruct-get 0 1",
+            "'Point' has no field 1",
+        ),
+        ("struct-new 0", "there is no struct 0"),
+        ("call-native 0", "there is no native 0"),
+        ("next 0", "'next' does not close a 'for' loop"),
+    ];
+
+    for (source, expected) in programs {
+        match Assembler::assemble(source, "bad.nasm") {
+            Ok(..) => panic!("'{source}' assembled"),
+            Err(err) => {
+                let message = err.to_string();
+                assert!(message.contains(expected), "'{source}': {message}");
+            }
+        }
+    }
+}
+
+#[test]
+fn loading_bad_operands_fails() {
+    let bytes = match Assembler::assemble(".const 0 i32 1\npush 0\ndup", "good.nasm") {
+        Ok(bytes) => bytes,
+        Err(err) => panic!("{err}"),
+    };
+
+    // point the push at a constant that doesn't exist, then replace the dup
+    let mut bad_constant = bytes.clone();
+    bad_constant[9] = 5;
+    let mut bad_opcode = bytes.clone();
+    bad_opcode[17] = u8::MAX;
+
+    for bytes in [bad_constant, bad_opcode] {
+        let mut vm = VirtualMachine::default();
+        match vm.load_bytes(bytes) {
+            Ok(..) => panic!("a corrupted binary loaded"),
+            Err(err) => assert_eq!(err.exit_code(), 3, "{err}"),
+        }
+    }
+}
+
+#[test]
+fn loading_truncated_binaries_fails() {
+    std::fs::create_dir_all("target/round_trip").unwrap();
+    std::fs::write(
+        "target/round_trip/sections.nere",
+        "struct Point { x y }\n[ 1 [ \"two\" ] ] . 1 2 Point .\n",
+    )
+    .unwrap();
+
+    let bytes = common::compile("target/round_trip/sections.nere");
+
+    // cutting the binary anywhere must fail to load instead of panicking,
+    // only the end of the natives (a stripped binary) and the full file load
+    let loaded = (0..=bytes.len())
+        .filter(|len| {
+            let mut vm = VirtualMachine::default();
+            vm.load_bytes(bytes[..*len].to_vec()).is_ok()
+        })
+        .count();
+
+    assert_eq!(loaded, 2);
+}
+
+#[test]
+fn loading_corrupt_lengths_fails() {
+    let section = |count: usize| count.to_ne_bytes().to_vec();
+
+    // a binary with only a halt and the given sections after it
+    let binary = |sections: &[Vec<u8>]| {
+        let mut bytes = section(0);
+        bytes.push(OpCode::Halt.as_byte());
+        bytes.extend(sections.concat());
+        bytes
+    };
+
+    let huge = section(usize::MAX);
+    let binaries = [
+        // a list constant that claims more items than any machine can hold
+        binary(&[section(1), vec![3], huge.clone()]),
+        // a string constant longer than the file
+        binary(&[section(1), vec![2], huge.clone()]),
+        // a list cut off after its first item
+        binary(&[section(1), vec![3], section(2), vec![0, 1, 0, 0, 0]]),
+        // a struct with more fields than the file holds
+        binary(&[
+            section(0),
+            section(1),
+            huge.clone(),
+            section(1),
+            b"P".to_vec(),
+        ]),
+        // an int constant missing its bytes
+        binary(&[section(1), vec![0, 1]]),
+        // an unknown constant type
+        binary(&[section(1), vec![9]]),
+    ];
+
+    for bytes in binaries {
+        let mut vm = VirtualMachine::default();
+        match vm.load_bytes(bytes) {
+            Ok(..) => panic!("a corrupted binary loaded"),
+            Err(err) => assert_eq!(err.exit_code(), 3, "{err}"),
+        }
+    }
+}
+
+#[test]
+fn jumping_to_next_outside_of_its_loop_fails() {
+    // the 'if' skips the 'for', so the loop was never entered
+    let source = ".const 0 i32 0\npush 0\nif L0027\nfor L0036\nL0027:\nnext L0027\nL0036:\nhalt";
+    let bytes = match Assembler::assemble(source, "next.nasm") {
+        Ok(bytes) => bytes,
+        Err(err) => panic!("{err}"),
+    };
+
+    let mut vm = VirtualMachine::default();
+    let result = vm
+        .load_bytes(bytes)
+        .and_then(|_| vm.execute(&RuntimeOptions::default()));
+
+    match result {
+        Ok(..) => panic!("'next' ran without a loop"),
+        Err(err) => assert!(
+            err.to_string()
+                .contains("'next' can only be used inside of a counted loop"),
+            "{err}"
+        ),
+    }
+}
@@ -0,0 +1,82 @@
+//! Runs 'nere test' on files with code outside of their test blocks and
+//! checks that it runs once, before each test starts on a fresh vm
+
+use std::process::{Command, Output};
+
+const DIR: &str = "target/test_runner";
+
+/// Writes each of `files` as a test file into its own directory under `DIR`
+/// and runs 'nere test' on that directory
+fn nere_test(dir: &str, files: &[(&str, &str)]) -> (Output, String) {
+    let dir = format!("{DIR}/{dir}");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for (name, source) in files.iter() {
+        std::fs::write(format!("{dir}/{name}_test.nere"), source).unwrap();
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nere"))
+        .args(["test", &dir])
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    (output, stdout)
+}
+
+const TWO_TESTS: &str = "\"setup\".\n1\n\n\
+                         test \"first\" {\n    1 assert-eq\n    2\n}\n\n\
+                         test \"second\" {\n    1 assert-eq\n}\n";
+
+#[test]
+fn top_level_code_runs_once() {
+    let (output, stdout) = nere_test("once", &[("once", TWO_TESTS)]);
+
+    assert!(output.status.success(), "{stdout}");
+    assert_eq!(
+        stdout.lines().filter(|line| *line == "setup").count(),
+        1,
+        "{stdout}"
+    );
+
+    // the value left by the first test isn't seen by the second one
+    assert!(stdout.contains("test first ... ok"), "{stdout}");
+    assert!(stdout.contains("test second ... ok"), "{stdout}");
+    assert!(stdout.contains("2 passed; 0 failed"), "{stdout}");
+}
+
+#[test]
+fn top_level_exit_fails_only_its_file() {
+    let exits = "\"before\".\n0 exit\n\ntest \"never\" { }\n";
+    let (output, stdout) = nere_test("exit", &[("exit", exits), ("later", TWO_TESTS)]);
+
+    assert!(!output.status.success(), "{stdout}");
+    assert_eq!(
+        stdout.lines().filter(|line| *line == "before").count(),
+        1,
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("exited with code 0 before the tests ran"),
+        "{stdout}"
+    );
+
+    // the file after the one that exited still runs
+    assert!(stdout.contains("2 passed; 1 failed"), "{stdout}");
+}
+
+#[test]
+fn each_test_runs_on_a_fresh_vm() {
+    // the quotation left by the top level code is still callable after a
+    // test that failed inside of another quotation
+    let source = "3 [: 2 * :]\n\n\
+                  test \"fails\" {\n    [: 1 0 / :] call\n}\n\n\
+                  test \"calls\" {\n    call 6 assert-eq\n}\n";
+    let (output, stdout) = nere_test("fresh", &[("fresh", source)]);
+
+    assert!(!output.status.success(), "{stdout}");
+    assert!(stdout.contains("test fails ... FAILED"), "{stdout}");
+    assert!(stdout.contains("test calls ... ok"), "{stdout}");
+    assert!(stdout.contains("1 passed; 1 failed"), "{stdout}");
+}
@@ -0,0 +1,62 @@
+//! Traces a short program as json lines and checks every line it writes
+
+mod common;
+
+use common::SharedBuffer;
+use nere_internal::virtual_machine::{RuntimeOptions, TraceFormat, VirtualMachine};
+
+const PROGRAM: &str = "target/trace/add.nere";
+
+#[test]
+fn json_trace_has_a_line_per_instruction() {
+    std::fs::create_dir_all("target/trace").unwrap();
+    std::fs::write(PROGRAM, "1 2 + .\n").unwrap();
+
+    let bytes = common::compile(PROGRAM);
+
+    let trace = SharedBuffer::default();
+    let mut vm = VirtualMachine::default();
+    vm.set_output(Box::new(std::io::sink()));
+    vm.set_trace_output(Box::new(trace.clone()));
+
+    let options = RuntimeOptions {
+        stack_trace: true,
+        trace_format: TraceFormat::Json,
+        ..Default::default()
+    };
+
+    if let Err(err) = vm.load_bytes(bytes).and_then(|_| vm.execute(&options)) {
+        panic!("{err}");
+    }
+
+    let trace = trace.text();
+    let lines = trace
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    let fields = lines
+        .iter()
+        .map(|line| {
+            (
+                line["ip"].as_u64().unwrap(),
+                line["opcode"].as_str().unwrap(),
+                line["stack"].to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // the stack is written after each instruction has run
+    assert_eq!(
+        fields,
+        [
+            (0, "push", "[1]".to_string()),
+            (9, "push", "[1,2]".to_string()),
+            (18, "add", "[3]".to_string()),
+            (19, "dump", "[]".to_string()),
+        ]
+    );
+
+    assert_eq!(lines[0]["operands"], serde_json::json!([0]));
+    assert_eq!(lines[1]["operands"], serde_json::json!([1]));
+}